@@ -0,0 +1,157 @@
+use std::{
+    future::Future,
+    io::Write as _,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use log::Level;
+use serde::Serialize;
+use shared::files::SyncMappingStats;
+
+use crate::{generate::plan::BuildPlan, publish::PublishStats};
+
+static JSON_EVENTS: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    // build name of the instance the current job works on
+    static INSTANCE: String;
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    InstanceStarted {
+        instance: &'a str,
+    },
+    InstanceFinished {
+        instance: &'a str,
+    },
+    InstanceFailed {
+        instance: &'a str,
+        error: String,
+    },
+    Stage {
+        instance: Option<String>,
+        stage: &'a str,
+    },
+    Progress {
+        instance: Option<String>,
+        message: &'a str,
+        position: u64,
+        length: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        unit: Option<&'a str>,
+    },
+    Warning {
+        instance: Option<String>,
+        message: String,
+    },
+    Error {
+        instance: Option<String>,
+        message: String,
+    },
+    Synced {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        instance: Option<&'a str>,
+        stats: SyncMappingStats,
+    },
+    Plan {
+        plan: &'a BuildPlan,
+    },
+    Published {
+        stats: PublishStats,
+    },
+    Finished {
+        error: Option<String>,
+    },
+}
+
+/// Print events as newline-delimited JSON to stdout instead of drawing progress bars.
+pub fn enable_json() {
+    JSON_EVENTS.store(true, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON_EVENTS.load(Ordering::Relaxed)
+}
+
+pub fn current_instance() -> Option<String> {
+    INSTANCE.try_with(|instance| instance.clone()).ok()
+}
+
+pub fn emit(event: Event) {
+    if !is_json() {
+        return;
+    }
+    let line = serde_json::to_string(&event).unwrap();
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{line}");
+    let _ = stdout.flush();
+}
+
+pub fn stage(stage: &str) {
+    emit(Event::Stage {
+        instance: current_instance(),
+        stage,
+    });
+}
+
+// reports the failure of a job that is dropped before it finishes,
+// jobs running next to a failed one are cancelled with it
+struct CancelGuard<'a> {
+    instance: &'a str,
+    finished: bool,
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            emit(Event::InstanceFailed {
+                instance: self.instance,
+                error: "Cancelled, another instance failed".to_string(),
+            });
+        }
+    }
+}
+
+/// Run an instance job, reporting its start and result.
+pub async fn instance_scope<T>(
+    instance: String,
+    job: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    emit(Event::InstanceStarted {
+        instance: &instance,
+    });
+    let mut guard = CancelGuard {
+        instance: &instance,
+        finished: false,
+    };
+    let result = INSTANCE.scope(instance.clone(), job).await;
+    guard.finished = true;
+    match &result {
+        Ok(_) => emit(Event::InstanceFinished {
+            instance: &instance,
+        }),
+        Err(e) => emit(Event::InstanceFailed {
+            instance: &instance,
+            error: format!("{e:#}"),
+        }),
+    }
+    result
+}
+
+// forwards warnings and errors from the logs
+pub fn log_hook(record: &log::Record) {
+    let message = record.args().to_string();
+    match record.level() {
+        Level::Warn => emit(Event::Warning {
+            instance: current_instance(),
+            message,
+        }),
+        Level::Error => emit(Event::Error {
+            instance: current_instance(),
+            message,
+        }),
+        _ => {}
+    }
+}
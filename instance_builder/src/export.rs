@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    iter,
+    path::{Path, PathBuf},
+};
+
+use serde::de::DeserializeOwned;
+use shared::{
+    mrpack::MrpackExporter,
+    paths::get_rel_object_path,
+    version::{
+        extra_version_metadata::ExtraVersionMetadata, version_manifest::VersionManifest,
+        version_metadata::VersionMetadata,
+    },
+};
+
+use crate::spec::get_manifest_path;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError {
+    #[error("Instance {0} not found in the version manifest")]
+    InstanceNotFound(String),
+    #[error("Instance {0} has no build made at {1}")]
+    BuildNotFound(String, u64),
+}
+
+// metadata of published builds is stored under its hash, the history keeps the objects
+// of older builds published
+async fn read_object<T: DeserializeOwned>(output_dir: &Path, sha1: &str) -> anyhow::Result<T> {
+    let data = tokio::fs::read(output_dir.join(get_rel_object_path(sha1))).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Export a build of an instance from the output directory as a Modrinth modpack.
+/// Included files are downloaded from the download server unless `embed` is set.
+pub async fn export_instance(
+    output_dir: &Path,
+    name: &str,
+    channel: Option<&str>,
+    built_at: Option<u64>,
+    archive_path: &Path,
+    embed: bool,
+) -> anyhow::Result<()> {
+    let manifest = VersionManifest::read_local(&get_manifest_path(output_dir)).await?;
+    let version_info = manifest
+        .versions
+        .iter()
+        .find(|version| version.get_name() == name && version.channel.as_deref() == channel)
+        .ok_or_else(|| ExportError::InstanceNotFound(name.to_string()))?;
+    let version_info = match built_at {
+        Some(built_at) => version_info
+            .get_build(built_at)
+            .ok_or_else(|| ExportError::BuildNotFound(name.to_string(), built_at))?,
+        None => version_info.without_history(),
+    };
+
+    let mut metadata: Vec<VersionMetadata> = vec![];
+    for sha1 in version_info
+        .inherits_from
+        .iter()
+        .map(|metadata_info| &metadata_info.sha1)
+        .chain(iter::once(&version_info.sha1))
+    {
+        metadata.push(read_object(output_dir, sha1).await?);
+    }
+
+    let mut exporter = MrpackExporter::new(&version_info, &metadata)?;
+    if let Some(sha1) = &version_info.extra_metadata_sha1 {
+        let extra_metadata: ExtraVersionMetadata = read_object(output_dir, sha1).await?;
+        let object_sources: HashMap<String, PathBuf> = extra_metadata
+            .include
+            .iter()
+            .flat_map(|include| include.objects.iter())
+            .map(|object| {
+                (
+                    object.sha1.clone(),
+                    output_dir.join(get_rel_object_path(&object.sha1)),
+                )
+            })
+            .collect();
+        exporter
+            .add_objects(&extra_metadata, &object_sources, embed)
+            .await?;
+    }
+    exporter.write(archive_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use shared::mrpack::{MRPACK_INDEX, MrpackIndex};
+
+    use super::*;
+
+    fn write_object(output_dir: &Path, sha1: &str, data: &str) {
+        let path = output_dir.join(get_rel_object_path(sha1));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+
+    fn extra_metadata(sha1: &str, path: &str) -> String {
+        format!(
+            r#"{{"include": [{{"path": "mods", "objects": [
+                {{"path": "{path}", "sha1": "{sha1}", "url": "https://example.com/objects/{}/{sha1}"}}
+            ]}}]}}"#,
+            &sha1[..2]
+        )
+    }
+
+    #[tokio::test]
+    async fn test_export_historic_build() {
+        let output_dir = std::env::temp_dir().join("export_historic_build_test");
+        let _ = std::fs::remove_dir_all(&output_dir);
+
+        let metadata = r#"{"id": "1.20.1", "libraries": [], "mainClass": "Main"}"#;
+        write_object(&output_dir, "aa01", metadata);
+        write_object(&output_dir, "bb01", &extra_metadata("cc01", "mods/old.jar"));
+        write_object(&output_dir, "cc01", "old mod");
+        write_object(&output_dir, "bb02", &extra_metadata("cc02", "mods/new.jar"));
+        write_object(&output_dir, "cc02", "new mod");
+
+        let url = |sha1: &str| format!("https://example.com/objects/{}/{sha1}", &sha1[..2]);
+        let manifest = format!(
+            r#"{{"versions": [{{
+                "id": "1.20.1", "url": "{}", "sha1": "aa01", "name": "Test",
+                "extra_metadata_url": "{}", "extra_metadata_sha1": "bb02",
+                "built_at": 200,
+                "history": [{{
+                    "built_at": 100, "id": "1.20.1", "url": "{}", "sha1": "aa01",
+                    "extra_metadata_url": "{}", "extra_metadata_sha1": "bb01"
+                }}]
+            }}]}}"#,
+            url("aa01"),
+            url("bb02"),
+            url("aa01"),
+            url("bb01")
+        );
+        std::fs::write(get_manifest_path(&output_dir), manifest).unwrap();
+
+        let archive_path = output_dir.join("test.mrpack");
+        export_instance(&output_dir, "Test", None, Some(100), &archive_path, false)
+            .await
+            .unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let index: MrpackIndex =
+            serde_json::from_reader(archive.by_name(MRPACK_INDEX).unwrap()).unwrap();
+        assert_eq!(index.version_id, "100");
+        assert_eq!(index.files.len(), 1);
+        assert_eq!(index.files[0].path, "mods/old.jar");
+        assert_eq!(index.files[0].file_size, Some(7));
+        assert!(index.files[0].hashes.sha512.is_some());
+
+        assert!(
+            export_instance(&output_dir, "Test", None, Some(150), &archive_path, false)
+                .await
+                .is_err()
+        );
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+}
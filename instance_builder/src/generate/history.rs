@@ -0,0 +1,387 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use shared::{
+    files::remove_empty_dirs,
+    paths::get_rel_object_path,
+    version::version_manifest::{HistoricBuild, VersionInfo},
+};
+use tokio::fs;
+
+const HISTORY_FILE: &str = "history.json";
+const HISTORY_OBJECTS_DIR: &str = "history_objects";
+
+#[derive(thiserror::Error, Debug)]
+pub enum HistoryError {
+    #[error("Failed to read build history {0}, fix or delete it to start a new history: {1}")]
+    InvalidHistory(PathBuf, serde_json::Error),
+}
+
+fn get_rel_history_dir(build_name: &str) -> PathBuf {
+    PathBuf::from("history").join(build_name)
+}
+
+// copies of the objects of recorded builds, shared by all instances,
+// their sources (include_from, generated metadata) change between builds
+fn get_history_object_path(work_dir: &Path, sha1: &str) -> PathBuf {
+    work_dir
+        .join(HISTORY_OBJECTS_DIR)
+        .join(&sha1[..2])
+        .join(sha1)
+}
+
+#[derive(Serialize, Deserialize)]
+struct HistoryRecord {
+    build: HistoricBuild,
+
+    // sha1s of the unversioned metadata, used to detect unchanged builds
+    source_sha1s: Vec<String>,
+
+    // path relative to output_dir -> source path in work_dir
+    files: BTreeMap<PathBuf, PathBuf>,
+}
+
+pub struct BuildSnapshot<'a> {
+    pub build_name: &'a str,
+    pub version_info: &'a VersionInfo,
+    // sha1 -> source of everything the build publishes under objects/
+    pub objects: &'a HashMap<String, PathBuf>,
+    // objects whose sources never change, they are published from the sources directly
+    pub stable_objects: HashSet<String>,
+    // other files of the build in output_dir -> source path in work_dir
+    pub files: HashMap<PathBuf, PathBuf>,
+}
+
+pub struct HistoryResult {
+    pub built_at: u64,
+    // older builds, newest first
+    pub history: Vec<HistoricBuild>,
+    // target -> source
+    pub mapping: HashMap<PathBuf, PathBuf>,
+}
+
+fn get_source_sha1s(version_info: &VersionInfo) -> Vec<String> {
+    let mut sha1s: Vec<String> = version_info
+        .inherits_from
+        .iter()
+        .map(|info| info.sha1.clone())
+        .collect();
+    sha1s.push(version_info.sha1.clone());
+    sha1s.extend(version_info.extra_metadata_sha1.clone());
+    sha1s
+}
+
+// a broken history fails the build, dropping it would unpublish the builds players are pinned to
+async fn read_history(history_path: &Path) -> anyhow::Result<Vec<HistoryRecord>> {
+    match fs::read(history_path).await {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| HistoryError::InvalidHistory(history_path.to_path_buf(), e).into()),
+        Err(_) => Ok(vec![]),
+    }
+}
+
+async fn copy_object(source: &Path, path: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(path.parent().unwrap()).await?;
+    // instances built in parallel can share objects, so the copy only appears once it is complete
+    let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::copy(source, &tmp_path).await?;
+    fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+// builds keep the urls they were published with, only their objects are copied out of the way,
+// a dry run points at the current sources instead
+async fn snapshot_build(
+    snapshot: &BuildSnapshot<'_>,
+    built_at: u64,
+    output_dir: &Path,
+    work_dir: &Path,
+    dry_run: bool,
+) -> anyhow::Result<HistoryRecord> {
+    let mut files = BTreeMap::new();
+    for (target, source) in &snapshot.files {
+        files.insert(
+            target.strip_prefix(output_dir)?.to_path_buf(),
+            source.clone(),
+        );
+    }
+
+    for (sha1, source) in snapshot.objects {
+        let source = if dry_run || snapshot.stable_objects.contains(sha1) {
+            source.clone()
+        } else {
+            let path = get_history_object_path(work_dir, sha1);
+            if !path.exists() {
+                copy_object(source, &path).await?;
+            }
+            path
+        };
+        files.insert(get_rel_object_path(sha1), source);
+    }
+
+    let version_info = snapshot.version_info;
+    Ok(HistoryRecord {
+        build: HistoricBuild {
+            built_at,
+            id: version_info.id.clone(),
+            url: version_info.url.clone(),
+            sha1: version_info.sha1.clone(),
+            inherits_from: version_info.inherits_from.clone(),
+            extra_metadata_url: version_info.extra_metadata_url.clone(),
+            extra_metadata_sha1: version_info.extra_metadata_sha1.clone(),
+        },
+        source_sha1s: get_source_sha1s(version_info),
+        files,
+    })
+}
+
+/// Record the build in the instance history and keep the last `history_size` builds published.
+/// With `dry_run`, the history is computed the same way but nothing in work_dir is changed.
+pub async fn update_history(
+    snapshot: BuildSnapshot<'_>,
+    history_size: usize,
+    output_dir: &Path,
+    work_dir: &Path,
+    dry_run: bool,
+) -> anyhow::Result<HistoryResult> {
+    let history_dir = work_dir.join(get_rel_history_dir(snapshot.build_name));
+    let history_path = history_dir.join(HISTORY_FILE);
+    let mut records = read_history(&history_path).await?;
+
+    // the manifest can't list builds that can't be published anymore
+    records.retain(|record| {
+        match record.files.values().find(|source| !source.exists()) {
+            Some(missing) => {
+                warn!(
+                    "Dropping build {} of {} from history, {} is missing from the working directory",
+                    record.build.built_at,
+                    snapshot.build_name,
+                    missing.display()
+                );
+                false
+            }
+            None => true,
+        }
+    });
+
+    let source_sha1s = get_source_sha1s(snapshot.version_info);
+    if records
+        .last()
+        .is_some_and(|record| record.source_sha1s == source_sha1s)
+    {
+        debug!("Build of {} is unchanged", snapshot.build_name);
+    } else {
+        let built_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if !dry_run {
+            info!("Recording build {built_at} of {}", snapshot.build_name);
+        }
+        let record = snapshot_build(&snapshot, built_at, output_dir, work_dir, dry_run).await?;
+        records.push(record);
+    }
+
+    if records.len() > history_size {
+        for record in records.drain(..records.len() - history_size) {
+            info!(
+                "Dropping build {} of {} from history",
+                record.build.built_at, snapshot.build_name
+            );
+        }
+    }
+
+    let mut mapping = HashMap::new();
+    for record in &records {
+        for (rel_path, source) in &record.files {
+            mapping.insert(output_dir.join(rel_path), source.clone());
+        }
+    }
+
+    if !dry_run {
+        fs::create_dir_all(&history_dir).await?;
+        fs::write(&history_path, serde_json::to_string(&records)?).await?;
+    }
+
+    let (latest, older) = records
+        .split_last()
+        .ok_or(anyhow::Error::msg("Empty build history"))?;
+    Ok(HistoryResult {
+        built_at: latest.build.built_at,
+        history: older
+            .iter()
+            .rev()
+            .map(|record| record.build.clone())
+            .collect(),
+        mapping,
+    })
+}
+
+/// Delete the copies of objects that no recorded build of any instance uses anymore.
+pub async fn remove_unused_history_objects(
+    work_dir: &Path,
+    used_sources: &HashSet<PathBuf>,
+) -> anyhow::Result<()> {
+    let objects_dir = work_dir.join(HISTORY_OBJECTS_DIR);
+    let Ok(mut prefix_dirs) = fs::read_dir(&objects_dir).await else {
+        return Ok(());
+    };
+    while let Some(prefix_dir) = prefix_dirs.next_entry().await? {
+        let mut read_dir = fs::read_dir(prefix_dir.path()).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            if !used_sources.contains(&entry.path()) {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+    }
+    remove_empty_dirs(&objects_dir).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestDirs {
+        output_dir: PathBuf,
+        work_dir: PathBuf,
+    }
+
+    impl TestDirs {
+        async fn new(name: &str) -> Self {
+            let temp_dir = std::env::temp_dir().join(name);
+            let _ = fs::remove_dir_all(&temp_dir).await;
+            let work_dir = temp_dir.join("work");
+            fs::create_dir_all(&work_dir).await.unwrap();
+            fs::write(work_dir.join("file"), "file").await.unwrap();
+            Self {
+                output_dir: temp_dir.join("output"),
+                work_dir,
+            }
+        }
+
+        fn get_history_path(&self) -> PathBuf {
+            self.work_dir
+                .join(get_rel_history_dir("Survival"))
+                .join(HISTORY_FILE)
+        }
+
+        // every build has its own object, named after the build, and shares a plain file
+        async fn update(&self, sha1: &str, history_size: usize, dry_run: bool) -> HistoryResult {
+            let object_source = self.work_dir.join(sha1);
+            fs::write(&object_source, sha1).await.unwrap();
+            let version_info = VersionInfo {
+                id: "Survival".to_string(),
+                url: format!("https://example.com/{sha1}.json"),
+                sha1: sha1.to_string(),
+                name: None,
+                inherits_from: vec![],
+                extra_metadata_url: None,
+                extra_metadata_sha1: None,
+                channel: None,
+                built_at: None,
+                history: vec![],
+            };
+            let snapshot = BuildSnapshot {
+                build_name: "Survival",
+                version_info: &version_info,
+                objects: &HashMap::from([(sha1.to_string(), object_source)]),
+                stable_objects: HashSet::new(),
+                files: HashMap::from([(self.output_dir.join("file"), self.work_dir.join("file"))]),
+            };
+            update_history(
+                snapshot,
+                history_size,
+                &self.output_dir,
+                &self.work_dir,
+                dry_run,
+            )
+            .await
+            .unwrap()
+        }
+
+        async fn remove(self) {
+            fs::remove_dir_all(self.work_dir.parent().unwrap())
+                .await
+                .unwrap();
+        }
+    }
+
+    fn get_history_sha1s(result: &HistoryResult) -> Vec<&str> {
+        result
+            .history
+            .iter()
+            .map(|build| build.sha1.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_build() {
+        let dirs = TestDirs::new("history_unchanged_test").await;
+        let first = dirs.update("build1", 3, false).await;
+        assert!(first.history.is_empty());
+        // objects are published from their copies, other files from their sources
+        assert_eq!(
+            first.mapping[&dirs.output_dir.join(get_rel_object_path("build1"))],
+            get_history_object_path(&dirs.work_dir, "build1")
+        );
+        assert_eq!(
+            first.mapping[&dirs.output_dir.join("file")],
+            dirs.work_dir.join("file")
+        );
+
+        let second = dirs.update("build1", 3, false).await;
+        assert_eq!(second.built_at, first.built_at);
+        assert!(second.history.is_empty());
+        dirs.remove().await;
+    }
+
+    #[tokio::test]
+    async fn test_history_size() {
+        let dirs = TestDirs::new("history_size_test").await;
+        dirs.update("build1", 2, false).await;
+        let result = dirs.update("build2", 2, false).await;
+        assert_eq!(get_history_sha1s(&result), ["build1"]);
+
+        let result = dirs.update("build3", 2, false).await;
+        assert_eq!(get_history_sha1s(&result), ["build2"]);
+        assert!(
+            !result
+                .mapping
+                .contains_key(&dirs.output_dir.join(get_rel_object_path("build1")))
+        );
+        dirs.remove().await;
+    }
+
+    #[tokio::test]
+    async fn test_missing_source() {
+        let dirs = TestDirs::new("history_missing_test").await;
+        dirs.update("build1", 3, false).await;
+        dirs.update("build2", 3, false).await;
+
+        fs::remove_file(get_history_object_path(&dirs.work_dir, "build1"))
+            .await
+            .unwrap();
+        let result = dirs.update("build3", 3, false).await;
+        assert_eq!(get_history_sha1s(&result), ["build2"]);
+        dirs.remove().await;
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let dirs = TestDirs::new("history_dry_run_test").await;
+        dirs.update("build1", 1, false).await;
+        let history = fs::read(dirs.get_history_path()).await.unwrap();
+
+        // a plan reports the new build without recording it or dropping the old one
+        let result = dirs.update("build2", 1, true).await;
+        assert!(result.history.is_empty());
+        assert_eq!(fs::read(dirs.get_history_path()).await.unwrap(), history);
+        assert!(!get_history_object_path(&dirs.work_dir, "build2").exists());
+
+        let result = dirs.update("build2", 2, false).await;
+        assert_eq!(get_history_sha1s(&result), ["build1"]);
+        dirs.remove().await;
+    }
+}
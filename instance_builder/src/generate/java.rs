@@ -0,0 +1,84 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::info;
+use shared::{
+    hash_cache::HashCache,
+    java::{download_archive, find_azul_archive},
+    paths::get_rel_object_path,
+    utils::{KeyedLocks, url_from_rel_path},
+    version::extra_version_metadata::JavaRuntime,
+};
+use tokio::fs;
+
+use crate::progress::BuilderProgressBar;
+
+#[derive(thiserror::Error, Debug)]
+pub enum JavaRuntimeError {
+    #[error("Invalid java platform {0}, expected <os>-<arch>, e.g. windows-x86_64")]
+    InvalidPlatform(String),
+    #[error("No Java {0} available for {1}")]
+    NotAvailable(String, String),
+}
+
+// same names as in library rules
+const OS_NAMES: [&str; 3] = ["windows", "linux", "osx"];
+const ARCHS: [&str; 2] = ["x86_64", "arm64"];
+
+fn get_java_runtimes_dir(work_dir: &Path) -> PathBuf {
+    work_dir.join("java_runtimes")
+}
+
+/// Download the Java runtimes for the given platforms into work_dir, so they can be
+/// published as objects. Archives are kept between builds and downloaded once.
+pub async fn download_java_runtimes(
+    java_version: &str,
+    platforms: &[String],
+    work_dir: &Path,
+    download_server_base: &str,
+    download_locks: &KeyedLocks,
+    hash_cache: &mut HashCache,
+) -> anyhow::Result<Vec<(JavaRuntime, PathBuf)>> {
+    let java_runtimes_dir = get_java_runtimes_dir(work_dir);
+    let mut runtimes = vec![];
+    for platform in platforms {
+        let (os_name, arch) = platform
+            .split_once('-')
+            .filter(|(os_name, arch)| OS_NAMES.contains(os_name) && ARCHS.contains(arch))
+            .ok_or_else(|| JavaRuntimeError::InvalidPlatform(platform.clone()))?;
+        let archive = find_azul_archive(java_version, os_name, arch)
+            .await?
+            .ok_or_else(|| {
+                JavaRuntimeError::NotAvailable(java_version.to_string(), platform.clone())
+            })?;
+
+        // azul archive names contain the exact release, a new release is downloaded as a new file
+        let path = java_runtimes_dir.join(&archive.filename);
+        // instances built in parallel often need the same runtimes
+        let _guard = download_locks.lock(&path.to_string_lossy()).await;
+        if !path.exists() {
+            info!("Downloading Java {java_version} for {platform}");
+            fs::create_dir_all(&java_runtimes_dir).await?;
+            download_archive(&archive.url, &path, Arc::new(BuilderProgressBar::new())).await?;
+        }
+
+        let sha1 = hash_cache.hash_file(&path).await?;
+        let size = fs::metadata(&path).await?.len();
+        let url = url_from_rel_path(&get_rel_object_path(&sha1), download_server_base)?;
+        runtimes.push((
+            JavaRuntime {
+                version: java_version.to_string(),
+                os: os_name.to_string(),
+                arch: arch.to_string(),
+                filename: archive.filename,
+                url,
+                sha1,
+                size: Some(size),
+            },
+            path,
+        ));
+    }
+    Ok(runtimes)
+}
@@ -1,3 +1,10 @@
+pub mod history;
+pub mod java;
 pub mod mapping;
+pub mod objects;
 pub mod patch;
+pub mod plan;
+pub mod remote;
+pub mod retention;
+pub mod server;
 pub mod sync;
@@ -0,0 +1,34 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use shared::{
+    paths::get_rel_object_path, utils::url_from_rel_path, version::version_manifest::VersionInfo,
+};
+
+/// Point the version info to copies of its metadata and extra metadata stored under their hashes,
+/// returns the sources of the copies by sha1.
+pub fn map_version_objects(
+    version_info: &mut VersionInfo,
+    get_metadata_source: impl Fn(&str) -> PathBuf,
+    extra_metadata_path: &Path,
+    download_server_base: &str,
+) -> anyhow::Result<HashMap<String, PathBuf>> {
+    let mut objects = HashMap::new();
+    let mut add_object = |source: PathBuf, sha1: &str| {
+        objects.insert(sha1.to_string(), source);
+        url_from_rel_path(&get_rel_object_path(sha1), download_server_base)
+    };
+
+    for metadata_info in version_info.inherits_from.iter_mut() {
+        metadata_info.url =
+            add_object(get_metadata_source(&metadata_info.id), &metadata_info.sha1)?;
+    }
+    version_info.url = add_object(get_metadata_source(&version_info.id), &version_info.sha1)?;
+    if let Some(sha1) = &version_info.extra_metadata_sha1 {
+        version_info.extra_metadata_url =
+            Some(add_object(extra_metadata_path.to_path_buf(), sha1)?);
+    }
+    Ok(objects)
+}
@@ -1,7 +1,9 @@
 use std::path::Path;
 
+use tokio::fs;
+
 use shared::{
-    files::hash_file,
+    hash_cache::HashCache,
     paths::{get_asset_index_path, get_client_jar_path, get_libraries_dir},
     utils::url_from_path,
     version::version_metadata::{Download, LibraryDownloads, VersionMetadata},
@@ -9,10 +11,19 @@ use shared::{
 
 use crate::utils::get_assets_dir;
 
+// loader metadata often has no sizes, take them from the downloaded files
+async fn fill_size(size: &mut Option<u64>, path: &Path) -> anyhow::Result<()> {
+    if size.is_none() && path.is_file() {
+        *size = Some(fs::metadata(path).await?.len());
+    }
+    Ok(())
+}
+
 pub async fn replace_download_urls(
     version_metadata: &mut VersionMetadata,
     download_server_base: &str,
     data_dir: &Path,
+    hash_cache: &mut HashCache,
 ) -> anyhow::Result<()> {
     let libraries_dir = get_libraries_dir(data_dir);
 
@@ -21,11 +32,13 @@ pub async fn replace_download_urls(
     {
         let client_path = get_client_jar_path(data_dir, &version_metadata.id);
         download.url = url_from_path(&client_path, data_dir, download_server_base)?;
+        fill_size(&mut download.size, &client_path).await?;
     }
 
     if let Some(asset_index) = &mut version_metadata.asset_index {
         let asset_index_path = get_asset_index_path(&get_assets_dir(data_dir), &asset_index.id);
         asset_index.url = url_from_path(&asset_index_path, data_dir, download_server_base)?;
+        fill_size(&mut asset_index.size, &asset_index_path).await?;
     }
 
     for library in &mut version_metadata.libraries {
@@ -33,12 +46,13 @@ pub async fn replace_download_urls(
             if let Some(downloads) = &mut library.downloads {
                 if let Some(artifact) = &mut downloads.artifact {
                     artifact.url = url_from_path(&library_path, data_dir, download_server_base)?;
+                    fill_size(&mut artifact.size, &library_path).await?;
                 }
             } else if library.url.is_some() {
                 let sha1 = if let Some(sha1) = &library.sha1 {
                     sha1.clone()
                 } else {
-                    hash_file(&library_path).await?
+                    hash_cache.hash_file(&library_path).await?
                 };
                 library.url = None;
                 library.sha1 = None;
@@ -46,6 +60,7 @@ pub async fn replace_download_urls(
                     artifact: Some(Download {
                         url: url_from_path(&library_path, data_dir, download_server_base)?,
                         sha1,
+                        size: Some(fs::metadata(&library_path).await?.len()),
                     }),
                     classifiers: None,
                 });
@@ -58,6 +73,7 @@ pub async fn replace_download_urls(
             && let Some(natives) = &downloads.classifiers
         {
             let mut new_natives_urls = vec![];
+            let mut natives_paths = vec![];
 
             for (native_name, download) in natives.clone() {
                 let natives_path = library.get_native_path(&libraries_dir, &native_name, &download);
@@ -66,6 +82,7 @@ pub async fn replace_download_urls(
                     data_dir,
                     download_server_base,
                 )?);
+                natives_paths.push(natives_path);
             }
 
             let natives = library
@@ -75,8 +92,13 @@ pub async fn replace_download_urls(
                 .classifiers
                 .as_mut()
                 .unwrap();
-            for (download, new_url) in natives.values_mut().zip(new_natives_urls) {
+            for ((download, new_url), natives_path) in natives
+                .values_mut()
+                .zip(new_natives_urls)
+                .zip(natives_paths)
+            {
                 download.url = new_url;
+                fill_size(&mut download.size, &natives_path).await?;
             }
         }
     }
@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use shared::{
+    files::MappingPlan,
+    version::version_manifest::{VersionInfo, VersionManifest},
+};
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceChange {
+    Added,
+    Changed,
+    Unchanged,
+    Removed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InstancePlan {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    pub change: InstanceChange,
+    pub old_sha1: Option<String>,
+    pub new_sha1: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BuildPlan {
+    // paths are relative to the output directory
+    pub added_files: Vec<PathBuf>,
+    pub changed_files: Vec<PathBuf>,
+    pub deleted_files: Vec<PathBuf>,
+    pub instances: Vec<InstancePlan>,
+    pub deleted_remote_instances: Vec<String>,
+}
+
+fn relative(output_dir: &Path, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    paths
+        .into_iter()
+        .map(|path| {
+            path.strip_prefix(output_dir)
+                .map(Path::to_path_buf)
+                .unwrap_or(path)
+        })
+        .collect()
+}
+
+fn find_version<'a>(
+    manifest: &'a VersionManifest,
+    version_info: &VersionInfo,
+) -> Option<&'a VersionInfo> {
+    manifest
+        .versions
+        .iter()
+        .find(|v| v.is_same_instance(version_info))
+}
+
+impl BuildPlan {
+    pub fn new(
+        output_dir: &Path,
+        files: MappingPlan,
+        old_manifest: &VersionManifest,
+        new_manifest: &VersionManifest,
+        deleted_remote_instances: Vec<String>,
+    ) -> Self {
+        let mut instances = vec![];
+        for new_version in &new_manifest.versions {
+            let old_version = find_version(old_manifest, new_version);
+            let change = match old_version {
+                None => InstanceChange::Added,
+                Some(old_version) if old_version == new_version => InstanceChange::Unchanged,
+                Some(_) => InstanceChange::Changed,
+            };
+            instances.push(InstancePlan {
+                name: new_version.get_name(),
+                channel: new_version.channel.clone(),
+                change,
+                old_sha1: old_version.map(|v| v.sha1.clone()),
+                new_sha1: Some(new_version.sha1.clone()),
+            });
+        }
+        for old_version in &old_manifest.versions {
+            if find_version(new_manifest, old_version).is_none() {
+                instances.push(InstancePlan {
+                    name: old_version.get_name(),
+                    channel: old_version.channel.clone(),
+                    change: InstanceChange::Removed,
+                    old_sha1: Some(old_version.sha1.clone()),
+                    new_sha1: None,
+                });
+            }
+        }
+
+        Self {
+            added_files: relative(output_dir, files.added),
+            changed_files: relative(output_dir, files.changed),
+            deleted_files: relative(output_dir, files.deleted),
+            instances,
+            deleted_remote_instances,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("Instances:");
+        for instance in &self.instances {
+            let sha1 = match (&instance.old_sha1, &instance.new_sha1) {
+                (Some(old), Some(new)) if old != new => format!("{old} -> {new}"),
+                (_, Some(new)) => new.clone(),
+                (Some(old), None) => old.clone(),
+                (None, None) => String::new(),
+            };
+            let change = match instance.change {
+                InstanceChange::Added => "added",
+                InstanceChange::Changed => "changed",
+                InstanceChange::Unchanged => "unchanged",
+                InstanceChange::Removed => "removed",
+            };
+            match &instance.channel {
+                Some(channel) => {
+                    println!("  {:<10} {} [{channel}] ({sha1})", change, instance.name)
+                }
+                None => println!("  {:<10} {} ({sha1})", change, instance.name),
+            }
+        }
+
+        if !self.deleted_remote_instances.is_empty() {
+            println!("Remote instances dropped by --delete-remote:");
+            for name in &self.deleted_remote_instances {
+                println!("  {name}");
+            }
+        }
+
+        for (title, paths, mark) in [
+            ("Added files", &self.added_files, '+'),
+            ("Changed files", &self.changed_files, '~'),
+            ("Deleted files", &self.deleted_files, '-'),
+        ] {
+            println!("{title} ({}):", paths.len());
+            for path in paths {
+                println!("  {mark} {}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::info;
+use sha2::{Digest as _, Sha512};
+use shared::{
+    adaptive_download::download_files,
+    files::{CheckEntry, get_download_entries_cached},
+    generate::extra::{IncludeRule, RemoteFile},
+    hash_cache::HashCache,
+    progress,
+};
+use tokio::fs;
+
+use crate::progress::BuilderProgressBar;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RemoteFileError {
+    #[error("sha512 of {0} doesn't match, expected {1}, got {2}")]
+    Sha512Mismatch(String, String, String),
+}
+
+fn get_remote_files_dir(work_dir: &Path) -> PathBuf {
+    work_dir.join("remote_files")
+}
+
+/// Download the files resolved from mod sources and the pinned downloads into work_dir
+/// and point the rules at them.
+/// Files are stored by sha1, so every version is downloaded once.
+pub async fn download_remote_files(
+    include: &mut [IncludeRule],
+    work_dir: &Path,
+    hash_cache: &mut HashCache,
+) -> anyhow::Result<()> {
+    // downloads are pinned by their hashes in the spec, they don't go through the lock
+    for rule in include.iter_mut() {
+        let downloads: Vec<RemoteFile> = rule
+            .downloads
+            .iter()
+            .map(|download| RemoteFile {
+                filename: download.path.clone(),
+                url: download.url.clone(),
+                sha1: download.sha1.clone(),
+                sha512: download.sha512.clone(),
+                size: download.size,
+                rehost: false,
+                client_only: download.client_only,
+                path: PathBuf::new(),
+            })
+            .collect();
+        rule.remote_files.extend(downloads);
+    }
+
+    let remote_files_dir = get_remote_files_dir(work_dir);
+    let mut check_entries = vec![];
+    for file in include
+        .iter_mut()
+        .flat_map(|rule| rule.remote_files.iter_mut())
+    {
+        file.path = remote_files_dir.join(&file.sha1);
+        check_entries.push(CheckEntry {
+            url: file.url.clone(),
+            remote_sha1: Some(file.sha1.clone()),
+            path: file.path.clone(),
+            size: file.size,
+        });
+    }
+    if check_entries.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&remote_files_dir).await?;
+    let download_entries = get_download_entries_cached(
+        check_entries,
+        hash_cache,
+        false,
+        progress::no_progress_bar(),
+    )
+    .await?;
+    if download_entries.is_empty() {
+        return Ok(());
+    }
+    let downloaded: HashSet<PathBuf> = download_entries
+        .iter()
+        .map(|entry| entry.path.clone())
+        .collect();
+    info!("Downloading {} included files", downloaded.len());
+    download_files(download_entries, Arc::new(BuilderProgressBar::new())).await?;
+
+    // sha1 is checked by the download already
+    for file in include.iter().flat_map(|rule| rule.remote_files.iter()) {
+        if let Some(sha512) = &file.sha512
+            && downloaded.contains(&file.path)
+        {
+            let actual = hex::encode(Sha512::digest(fs::read(&file.path).await?));
+            if actual != *sha512 {
+                fs::remove_file(&file.path).await?;
+                return Err(RemoteFileError::Sha512Mismatch(
+                    file.filename.clone(),
+                    sha512.clone(),
+                    actual,
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
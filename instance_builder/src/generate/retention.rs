@@ -0,0 +1,71 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+const RETIRED_FILES_FILE: &str = "retired_files.json";
+
+#[derive(Serialize, Deserialize, Default)]
+struct RetiredFiles {
+    // path relative to output_dir -> unix time when the file stopped being published
+    files: BTreeMap<PathBuf, u64>,
+}
+
+/// Keeps files of replaced builds in the output directory for a grace period,
+/// launchers that fetched the previous manifest may still be downloading them.
+pub struct Retention {
+    path: PathBuf,
+    retired: RetiredFiles,
+    grace_period_secs: u64,
+}
+
+impl Retention {
+    pub async fn load(work_dir: &Path, grace_period_hours: u64) -> Self {
+        let path = work_dir.join(RETIRED_FILES_FILE);
+        let retired = match fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("Failed to read retired files, starting from scratch: {e}");
+                RetiredFiles::default()
+            }),
+            Err(_) => RetiredFiles::default(),
+        };
+        Self {
+            path,
+            retired,
+            grace_period_secs: grace_period_hours * 60 * 60,
+        }
+    }
+
+    /// Record `unmapped_files` of `output_dir` as retired and return the ones whose grace period is over.
+    pub fn expire(
+        &mut self,
+        output_dir: &Path,
+        unmapped_files: Vec<PathBuf>,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // files that are published again are forgotten
+        let mut retired_files = BTreeMap::new();
+        let mut expired_files = vec![];
+        for path in unmapped_files {
+            let rel_path = path.strip_prefix(output_dir)?.to_path_buf();
+            let retired_at = self.retired.files.get(&rel_path).copied().unwrap_or(now);
+            if now.saturating_sub(retired_at) >= self.grace_period_secs {
+                expired_files.push(path);
+            } else {
+                retired_files.insert(rel_path, retired_at);
+            }
+        }
+        self.retired.files = retired_files;
+        Ok(expired_files)
+    }
+
+    pub async fn save(&self) -> anyhow::Result<()> {
+        fs::write(&self.path, serde_json::to_string(&self.retired)?).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::info;
+use shared::{
+    adaptive_download::download_files,
+    files::{self, get_download_entries},
+    generate::extra::IncludeRule,
+    loader_generator::{
+        fabric::{download_fabric_server_launcher, get_fabric_version},
+        forge::{
+            Loader, get_forge_version, get_full_version, get_vanilla_java_version,
+            install_forge_server,
+        },
+        generator::SERVER_LOCK_FILE,
+        quilt::{get_quilt_version, install_quilt_server},
+    },
+    path_filter::PathFilter,
+    paths::{get_java_dir, get_versions_dir},
+    progress,
+    version::{version_manifest::VersionInfo, version_metadata::VersionMetadata},
+};
+use tokio::fs;
+
+use crate::{progress::BuilderProgressBar, spec::Instances};
+
+const SERVER_JAR: &str = "server.jar";
+const FABRIC_LAUNCHER_JAR: &str = "fabric-server-launch.jar";
+const QUILT_LAUNCHER_JAR: &str = "quilt-server-launch.jar";
+const DEFAULT_XMX: &str = "4096M";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ServerError {
+    #[error("Minecraft {0} has no server download")]
+    NoServerDownload(String),
+    #[error("Unsupported loader name: {0}")]
+    UnsupportedLoader(String),
+    #[error("{0} server jar not found after installation")]
+    NoServerJar(String),
+}
+
+enum Launch {
+    Jar(String),
+    // newer (neo)forge servers are started by run scripts that read user_jvm_args.txt
+    ForgeScript,
+}
+
+fn get_servers_work_dir(work_dir: &Path) -> PathBuf {
+    work_dir.join(".server")
+}
+
+// "8192" and "8192M" mean the same thing in the spec
+fn get_xmx(recommended_xmx: Option<&str>) -> String {
+    match recommended_xmx {
+        Some(xmx) if xmx.ends_with(|c: char| c.is_ascii_digit()) => format!("{xmx}M"),
+        Some(xmx) => xmx.to_string(),
+        None => DEFAULT_XMX.to_string(),
+    }
+}
+
+async fn get_vanilla_server_jar(
+    vanilla_metadata: &VersionMetadata,
+    servers_work_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let download = vanilla_metadata
+        .downloads
+        .as_ref()
+        .and_then(|downloads| downloads.server.as_ref())
+        .ok_or(ServerError::NoServerDownload(vanilla_metadata.id.clone()))?;
+
+    let path = servers_work_dir
+        .join("vanilla")
+        .join(&vanilla_metadata.id)
+        .join(SERVER_JAR);
+    fs::create_dir_all(path.parent().unwrap()).await?;
+    let download_entries = get_download_entries(
+        vec![download.get_check_entry(&path)],
+        progress::no_progress_bar(),
+    )
+    .await?;
+    if !download_entries.is_empty() {
+        info!("Downloading minecraft {} server", vanilla_metadata.id);
+        download_files(download_entries, Arc::new(BuilderProgressBar::new())).await?;
+    }
+    Ok(path)
+}
+
+fn map_installed_dir(
+    mapping: &mut HashMap<PathBuf, PathBuf>,
+    installed_dir: &Path,
+    server_dir: &Path,
+) -> anyhow::Result<()> {
+    for file in files::get_files_in_dir(installed_dir)? {
+        let rel_path = file.strip_prefix(installed_dir)?;
+        if rel_path != Path::new(SERVER_LOCK_FILE) {
+            mapping.insert(server_dir.join(rel_path), file.clone());
+        }
+    }
+    Ok(())
+}
+
+// old (neo)forge versions have no run scripts and are started from the universal jar
+fn find_forge_server_jar(installed_dir: &Path, loader: &Loader) -> anyhow::Result<String> {
+    let prefix = format!("{loader:?}-");
+    for entry in std::fs::read_dir(installed_dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if name.starts_with(&prefix) && name.ends_with(".jar") && !name.contains("installer") {
+            return Ok(name);
+        }
+    }
+    Err(ServerError::NoServerJar(loader.to_string()).into())
+}
+
+fn get_include_mapping(
+    include: &[IncludeRule],
+    include_from: Option<&Path>,
+    server_dir: &Path,
+) -> anyhow::Result<HashMap<PathBuf, PathBuf>> {
+    let mut mapping = HashMap::new();
+    let mut existing_paths = HashSet::new();
+    for rule in include {
+        if rule.client_only {
+            if let Some(include_from) = include_from {
+                existing_paths.insert(include_from.join(&rule.path));
+            }
+            continue;
+        }
+        // files in include_from take precedence, like in the client instance
+        for file in rule.remote_files.iter().filter(|file| !file.client_only) {
+            mapping.insert(
+                server_dir.join(&rule.path).join(&file.filename),
+                file.path.clone(),
+            );
+        }
+        if let Some(include_from) = include_from {
+            let from = include_from.join(&rule.path);
+            let path_filter = PathFilter::new(&rule.include, &rule.exclude)?;
+            for file in files::get_files_ignore_paths(&from, &existing_paths)? {
+                let rel_path = file.strip_prefix(include_from)?;
+                if path_filter.matches(&rel_path.to_string_lossy()) {
+                    mapping.insert(server_dir.join(rel_path), file.clone());
+                }
+            }
+            existing_paths.insert(from);
+        }
+    }
+    Ok(mapping)
+}
+
+async fn write_start_scripts(
+    scripts_dir: &Path,
+    launch: &Launch,
+    xmx: &str,
+) -> anyhow::Result<Vec<PathBuf>> {
+    fs::create_dir_all(scripts_dir).await?;
+
+    let (sh_command, bat_command) = match launch {
+        Launch::Jar(jar) => (
+            format!("exec java -Xmx{xmx} -jar {jar} nogui \"$@\""),
+            format!("java -Xmx{xmx} -jar {jar} nogui %*"),
+        ),
+        Launch::ForgeScript => (
+            "exec sh ./run.sh nogui \"$@\"".to_string(),
+            "call run.bat nogui %*".to_string(),
+        ),
+    };
+
+    let sh_path = scripts_dir.join("start.sh");
+    fs::write(
+        &sh_path,
+        format!("#!/bin/sh\ncd \"$(dirname \"$0\")\"\n{sh_command}\n"),
+    )
+    .await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        fs::set_permissions(&sh_path, std::fs::Permissions::from_mode(0o755)).await?;
+    }
+
+    let bat_path = scripts_dir.join("start.bat");
+    fs::write(
+        &bat_path,
+        format!("@echo off\r\ncd /d \"%~dp0\"\r\n{bat_command}\r\n"),
+    )
+    .await?;
+
+    let mut paths = vec![sh_path, bat_path];
+    if let Launch::ForgeScript = launch {
+        let jvm_args_path = scripts_dir.join("user_jvm_args.txt");
+        fs::write(&jvm_args_path, format!("-Xmx{xmx}\n")).await?;
+        paths.push(jvm_args_path);
+    }
+    Ok(paths)
+}
+
+/// Prepare a dedicated server for the instance, returns the mapping (target -> source) of `server_dir`.
+pub async fn build_server(
+    instance: &Instances,
+    build_name: &str,
+    vanilla_version_info: &VersionInfo,
+    server_dir: &Path,
+    work_dir: &Path,
+) -> anyhow::Result<HashMap<PathBuf, PathBuf>> {
+    let servers_work_dir = get_servers_work_dir(work_dir);
+    let minecraft_version = &vanilla_version_info.id;
+    let progress_bar = Arc::new(BuilderProgressBar::new());
+
+    info!("Downloading vanilla version metadata");
+    let vanilla_metadata = VersionMetadata::read_or_download(
+        &vanilla_version_info.get_parent_metadata_info(),
+        &get_versions_dir(work_dir),
+    )
+    .await?;
+
+    let mut mapping = HashMap::new();
+    let loader = match instance.loader_name.as_str() {
+        "forge" => Some(Loader::Forge),
+        "neoforge" => Some(Loader::Neoforge),
+        _ => None,
+    };
+    let launch = if let Some(loader) = loader {
+        // the installer downloads the vanilla server by itself
+        let forge_version =
+            get_forge_version(minecraft_version, &instance.loader_version, &loader).await?;
+        info!("Installing {loader} {forge_version} server");
+        let installed_dir = servers_work_dir
+            .join(format!("{loader:?}"))
+            .join(get_full_version(minecraft_version, &forge_version));
+        install_forge_server(
+            &installed_dir,
+            &get_java_dir(work_dir),
+            &forge_version,
+            &vanilla_metadata,
+            &loader,
+            progress_bar,
+        )
+        .await?;
+        map_installed_dir(&mut mapping, &installed_dir, server_dir)?;
+
+        if installed_dir.join("run.sh").exists() {
+            Launch::ForgeScript
+        } else {
+            Launch::Jar(find_forge_server_jar(&installed_dir, &loader)?)
+        }
+    } else {
+        let server_jar = get_vanilla_server_jar(&vanilla_metadata, &servers_work_dir).await?;
+        mapping.insert(server_dir.join(SERVER_JAR), server_jar);
+
+        match instance.loader_name.as_str() {
+            "vanilla" => Launch::Jar(SERVER_JAR.to_string()),
+
+            "fabric" => {
+                let fabric_version =
+                    get_fabric_version(minecraft_version, &instance.loader_version).await?;
+                let launcher_path = servers_work_dir
+                    .join("fabric")
+                    .join(format!("{minecraft_version}-{fabric_version}"))
+                    .join(FABRIC_LAUNCHER_JAR);
+                if !launcher_path.exists() {
+                    info!("Downloading Fabric {fabric_version} server launcher");
+                    download_fabric_server_launcher(
+                        minecraft_version,
+                        &fabric_version,
+                        &launcher_path,
+                    )
+                    .await?;
+                }
+                mapping.insert(server_dir.join(FABRIC_LAUNCHER_JAR), launcher_path);
+                Launch::Jar(FABRIC_LAUNCHER_JAR.to_string())
+            }
+
+            "quilt" => {
+                let quilt_version =
+                    get_quilt_version(minecraft_version, &instance.loader_version).await?;
+                info!("Installing Quilt {quilt_version} server");
+                let installed_dir = servers_work_dir
+                    .join("quilt")
+                    .join(format!("{minecraft_version}-{quilt_version}"));
+                let java_version = get_vanilla_java_version(&vanilla_metadata)
+                    .await?
+                    .unwrap_or("8".to_string());
+                install_quilt_server(
+                    &installed_dir,
+                    &get_java_dir(work_dir),
+                    minecraft_version,
+                    &quilt_version,
+                    &java_version,
+                    progress_bar,
+                )
+                .await?;
+                map_installed_dir(&mut mapping, &installed_dir, server_dir)?;
+                Launch::Jar(QUILT_LAUNCHER_JAR.to_string())
+            }
+
+            _ => return Err(ServerError::UnsupportedLoader(instance.loader_name.clone()).into()),
+        }
+    };
+
+    mapping.extend(get_include_mapping(
+        &instance.include,
+        instance.include_from.as_deref().map(Path::new),
+        server_dir,
+    )?);
+
+    let scripts_dir = servers_work_dir.join("instances").join(build_name);
+    let xmx = get_xmx(instance.recommended_xmx.as_deref());
+    for path in write_start_scripts(&scripts_dir, &launch, &xmx).await? {
+        mapping.insert(server_dir.join(path.file_name().unwrap()), path);
+    }
+
+    Ok(mapping)
+}
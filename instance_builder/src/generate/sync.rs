@@ -13,7 +13,7 @@ use shared::{
     version::{asset_metadata::AssetsMetadata, version_metadata::VersionMetadata},
 };
 
-use crate::{progress::TerminalProgressBar, utils::get_assets_dir};
+use crate::{progress::BuilderProgressBar, utils::get_assets_dir};
 
 pub fn get_libraries_check_downloads(
     version_metadata: &VersionMetadata,
@@ -76,7 +76,7 @@ pub async fn sync_version(
         check_entries.push(client_entry);
     }
 
-    let progress_bar = Arc::new(TerminalProgressBar::new());
+    let progress_bar = Arc::new(BuilderProgressBar::new());
 
     let all_paths = check_entries
         .iter()
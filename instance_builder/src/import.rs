@@ -0,0 +1,640 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::File,
+    io::Read as _,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use reqwest::Client;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use shared::{
+    generate::extra::IncludeDownload,
+    mrpack::{MRPACK_INDEX, MrpackIndex},
+};
+use zip::ZipArchive;
+
+use crate::spec::Instances;
+
+const CURSEFORGE_MANIFEST: &str = "manifest.json";
+
+pub const DEFAULT_CURSEFORGE_API_URL: &str = "https://api.curseforge.com/v1";
+
+// curseforge class ids of the project types that go somewhere else than mods
+const CURSEFORGE_RESOURCE_PACKS: u64 = 12;
+const CURSEFORGE_SHADER_PACKS: u64 = 6552;
+const CURSEFORGE_SHA1: u64 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("{0} is neither a Modrinth (.mrpack) nor a CurseForge modpack")]
+    UnknownFormat(String),
+    #[error("The modpack doesn't specify a minecraft version")]
+    NoMinecraftVersion,
+    #[error("Unsupported modpack dependency {0}")]
+    UnsupportedDependency(String),
+    #[error("Unsafe file path in the modpack: {0}")]
+    UnsafePath(String),
+    #[error("{0} already exists and is not empty")]
+    OutputExists(PathBuf),
+    #[error(
+        "Importing CurseForge modpacks needs an API key, set it with --curseforge-api-key or CURSEFORGE_API_KEY"
+    )]
+    MissingCurseforgeApiKey,
+    #[error("CurseForge file {0} not found")]
+    CurseforgeFileNotFound(u64),
+    #[error("CurseForge file {0} has no sha1")]
+    CurseforgeNoSha1(String),
+}
+
+#[derive(Deserialize)]
+struct CurseforgeModLoader {
+    id: String,
+    #[serde(default)]
+    primary: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseforgeMinecraft {
+    version: String,
+    #[serde(default)]
+    mod_loaders: Vec<CurseforgeModLoader>,
+}
+
+#[derive(Deserialize)]
+struct CurseforgeFileRef {
+    #[serde(rename = "projectID")]
+    project_id: u64,
+    #[serde(rename = "fileID")]
+    file_id: u64,
+}
+
+#[derive(Deserialize)]
+struct CurseforgeManifest {
+    minecraft: CurseforgeMinecraft,
+    name: String,
+    files: Vec<CurseforgeFileRef>,
+    overrides: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CurseforgeHash {
+    value: String,
+    algo: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseforgeFile {
+    id: u64,
+    file_name: String,
+    file_length: u64,
+    download_url: Option<String>,
+    hashes: Vec<CurseforgeHash>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseforgeMod {
+    id: u64,
+    class_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CurseforgeResponse<T> {
+    data: Vec<T>,
+}
+
+pub struct CurseforgeOptions {
+    pub api_url: String,
+    pub api_key: Option<String>,
+}
+
+// a file of the pack that players download from its original url
+struct PackFile {
+    path: String,
+    url: String,
+    sha1: String,
+    sha512: Option<String>,
+    size: Option<u64>,
+    client_only: bool,
+}
+
+struct Pack {
+    name: String,
+    minecraft_version: String,
+    loader_name: String,
+    loader_version: Option<String>,
+    files: Vec<PackFile>,
+    // directories of the archive that are extracted into include_from
+    override_dirs: Vec<String>,
+}
+
+fn read_entry<T: DeserializeOwned>(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+) -> anyhow::Result<Option<T>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut data = vec![];
+    file.read_to_end(&mut data)?;
+    Ok(Some(serde_json::from_slice(&data)?))
+}
+
+// only plain relative paths, so a pack can't write outside of include_from
+fn check_path(path: &str) -> anyhow::Result<()> {
+    let is_safe = Path::new(path)
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)));
+    if !is_safe || path.contains('\\') {
+        return Err(ImportError::UnsafePath(path.to_string()).into());
+    }
+    Ok(())
+}
+
+fn parse_mrpack(index: MrpackIndex) -> anyhow::Result<Pack> {
+    let mut minecraft_version = None;
+    let mut loader = None;
+    for (dependency, version) in index.dependencies {
+        let loader_name = match dependency.as_str() {
+            "minecraft" => {
+                minecraft_version = Some(version);
+                continue;
+            }
+            "fabric-loader" => "fabric",
+            "quilt-loader" => "quilt",
+            "forge" => "forge",
+            "neoforge" => "neoforge",
+            _ => return Err(ImportError::UnsupportedDependency(dependency).into()),
+        };
+        loader = Some((loader_name.to_string(), version));
+    }
+
+    let mut files = vec![];
+    for file in index.files {
+        check_path(&file.path)?;
+        let (client, server) = match &file.env {
+            Some(env) => (env.client.as_str(), env.server.as_str()),
+            None => ("required", "required"),
+        };
+        if client == "unsupported" {
+            info!("Skipping server-only file {}", file.path);
+            continue;
+        }
+        let Some(url) = file.downloads.into_iter().next() else {
+            warn!("Skipping {}, it has no download url", file.path);
+            continue;
+        };
+        files.push(PackFile {
+            path: file.path,
+            url,
+            sha1: file.hashes.sha1,
+            sha512: file.hashes.sha512,
+            size: file.file_size,
+            client_only: server == "unsupported",
+        });
+    }
+
+    let (loader_name, loader_version) = match loader {
+        Some((loader_name, version)) => (loader_name, Some(version)),
+        None => ("vanilla".to_string(), None),
+    };
+    Ok(Pack {
+        name: index.name,
+        minecraft_version: minecraft_version.ok_or(ImportError::NoMinecraftVersion)?,
+        loader_name,
+        loader_version,
+        files,
+        override_dirs: vec!["overrides".to_string(), "client-overrides".to_string()],
+    })
+}
+
+async fn curseforge_post<T: DeserializeOwned>(
+    client: &Client,
+    options: &CurseforgeOptions,
+    api_key: &str,
+    path: &str,
+    body: Value,
+) -> anyhow::Result<Vec<T>> {
+    let response: CurseforgeResponse<T> = client
+        .post(format!("{}{path}", options.api_url.trim_end_matches('/')))
+        .header("x-api-key", api_key)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response.data)
+}
+
+async fn parse_curseforge(
+    manifest: CurseforgeManifest,
+    options: &CurseforgeOptions,
+) -> anyhow::Result<Pack> {
+    let loader = manifest
+        .minecraft
+        .mod_loaders
+        .iter()
+        .find(|loader| loader.primary)
+        .or(manifest.minecraft.mod_loaders.first());
+    let (loader_name, loader_version) = match loader {
+        // e.g. "forge-47.2.0"
+        Some(loader) => match loader.id.split_once('-') {
+            Some((loader_name @ ("forge" | "neoforge" | "fabric" | "quilt"), version)) => {
+                (loader_name.to_string(), Some(version.to_string()))
+            }
+            _ => return Err(ImportError::UnsupportedDependency(loader.id.clone()).into()),
+        },
+        None => ("vanilla".to_string(), None),
+    };
+
+    let mut files = vec![];
+    if !manifest.files.is_empty() {
+        let api_key = options
+            .api_key
+            .as_deref()
+            .ok_or(ImportError::MissingCurseforgeApiKey)?;
+        let client = Client::new();
+        info!(
+            "Fetching {} files from the CurseForge API",
+            manifest.files.len()
+        );
+        let file_ids: Vec<u64> = manifest.files.iter().map(|file| file.file_id).collect();
+        let mod_ids: Vec<u64> = manifest.files.iter().map(|file| file.project_id).collect();
+        let cf_files: HashMap<u64, CurseforgeFile> = curseforge_post::<CurseforgeFile>(
+            &client,
+            options,
+            api_key,
+            "/mods/files",
+            json!({ "fileIds": file_ids }),
+        )
+        .await?
+        .into_iter()
+        .map(|file| (file.id, file))
+        .collect();
+        let class_ids: HashMap<u64, Option<u64>> = curseforge_post::<CurseforgeMod>(
+            &client,
+            options,
+            api_key,
+            "/mods",
+            json!({ "modIds": mod_ids }),
+        )
+        .await?
+        .into_iter()
+        .map(|cf_mod| (cf_mod.id, cf_mod.class_id))
+        .collect();
+
+        for file_ref in &manifest.files {
+            let file = cf_files
+                .get(&file_ref.file_id)
+                .ok_or(ImportError::CurseforgeFileNotFound(file_ref.file_id))?;
+            let dir = match class_ids.get(&file_ref.project_id).copied().flatten() {
+                Some(CURSEFORGE_RESOURCE_PACKS) => "resourcepacks",
+                Some(CURSEFORGE_SHADER_PACKS) => "shaderpacks",
+                _ => "mods",
+            };
+            let path = format!("{dir}/{}", file.file_name);
+            check_path(&path)?;
+            // authors can forbid third-party downloads, such files can't be added automatically
+            let Some(url) = &file.download_url else {
+                warn!(
+                    "{} can't be downloaded through the CurseForge API, put it into {dir} of include_from by hand",
+                    file.file_name
+                );
+                continue;
+            };
+            let sha1 = file
+                .hashes
+                .iter()
+                .find(|hash| hash.algo == CURSEFORGE_SHA1)
+                .ok_or_else(|| ImportError::CurseforgeNoSha1(file.file_name.clone()))?;
+            files.push(PackFile {
+                path,
+                url: url.clone(),
+                sha1: sha1.value.clone(),
+                sha512: None,
+                size: Some(file.file_length),
+                client_only: false,
+            });
+        }
+    }
+
+    Ok(Pack {
+        name: manifest.name,
+        minecraft_version: manifest.minecraft.version,
+        loader_name,
+        loader_version,
+        files,
+        override_dirs: vec![manifest.overrides.unwrap_or("overrides".to_string())],
+    })
+}
+
+// returns the top-level names of the extracted files
+fn extract_overrides(
+    archive: &mut ZipArchive<File>,
+    override_dirs: &[String],
+    include_from: &Path,
+) -> anyhow::Result<BTreeSet<String>> {
+    let mut names = BTreeSet::new();
+    // later directories overwrite files of the earlier ones
+    for dir in override_dirs {
+        let prefix = format!("{dir}/");
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            let Some(rel_path) = entry.name().strip_prefix(&prefix).map(str::to_string) else {
+                continue;
+            };
+            if entry.is_dir() || rel_path.is_empty() {
+                continue;
+            }
+            check_path(&rel_path)?;
+
+            let path = include_from.join(&rel_path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::io::copy(&mut entry, &mut File::create(&path)?)?;
+            names.insert(rel_path.split('/').next().unwrap().to_string());
+        }
+    }
+    Ok(names)
+}
+
+fn get_include_rules(override_names: BTreeSet<String>, files: &[PackFile]) -> Vec<Value> {
+    // downloads are grouped by their top-level directory, so rules don't overlap
+    let mut downloads: BTreeMap<String, Vec<IncludeDownload>> = BTreeMap::new();
+    for file in files {
+        let Some((dir, path)) = file.path.split_once('/') else {
+            warn!(
+                "Skipping {}, files in the instance root are not supported",
+                file.path
+            );
+            continue;
+        };
+        downloads
+            .entry(dir.to_string())
+            .or_default()
+            .push(IncludeDownload {
+                path: path.to_string(),
+                url: file.url.clone(),
+                sha1: file.sha1.clone(),
+                sha512: file.sha512.clone(),
+                size: file.size,
+                client_only: file.client_only,
+            });
+    }
+
+    let mut paths: BTreeSet<String> = override_names;
+    paths.extend(downloads.keys().cloned());
+    paths
+        .into_iter()
+        .map(|path| match downloads.remove(&path) {
+            Some(downloads) => json!({ "path": path, "downloads": downloads }),
+            None => json!({ "path": path }),
+        })
+        .collect()
+}
+
+/// Read a Modrinth or CurseForge modpack, extract its overrides to `include_from`
+/// and return the instance entry for the spec.
+pub async fn import_modpack(
+    archive_path: &Path,
+    include_from: &Path,
+    name: Option<&str>,
+    curseforge: &CurseforgeOptions,
+) -> anyhow::Result<Value> {
+    if include_from.exists() && include_from.read_dir()?.next().is_some() {
+        return Err(ImportError::OutputExists(include_from.to_path_buf()).into());
+    }
+
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+    let pack = if let Some(index) = read_entry::<MrpackIndex>(&mut archive, MRPACK_INDEX)? {
+        info!("Importing Modrinth modpack {}", index.name);
+        parse_mrpack(index)?
+    } else if let Some(manifest) =
+        read_entry::<CurseforgeManifest>(&mut archive, CURSEFORGE_MANIFEST)?
+    {
+        info!("Importing CurseForge modpack {}", manifest.name);
+        parse_curseforge(manifest, curseforge).await?
+    } else {
+        return Err(ImportError::UnknownFormat(archive_path.display().to_string()).into());
+    };
+
+    std::fs::create_dir_all(include_from)?;
+    let override_names = extract_overrides(&mut archive, &pack.override_dirs, include_from)?;
+    info!(
+        "Extracted overrides to {}, {} files are downloaded from their original urls",
+        include_from.display(),
+        pack.files.len()
+    );
+
+    let mut instance = json!({
+        "name": name.unwrap_or(&pack.name),
+        "minecraft_version": pack.minecraft_version,
+        "loader_name": pack.loader_name,
+    });
+    if let Some(loader_version) = pack.loader_version {
+        instance["loader_version"] = json!(loader_version);
+    }
+    instance["include_from"] = json!(include_from.to_string_lossy());
+    instance["include"] = Value::Array(get_include_rules(override_names, &pack.files));
+
+    // only the set fields are written, the rest keeps the spec defaults,
+    // so the entry is checked against the spec before it is used
+    serde_json::from_value::<Instances>(instance.clone())?;
+    Ok(instance)
+}
+
+/// Add the instance to the spec file, replacing the instance with the same name.
+pub fn add_to_spec(spec_path: &Path, instance: Value) -> anyhow::Result<()> {
+    let mut spec: Value = serde_json::from_str(&std::fs::read_to_string(spec_path)?)?;
+    if !spec["instances"].is_array() {
+        spec["instances"] = json!([]);
+    }
+    let instances = spec["instances"].as_array_mut().unwrap();
+    let same_instance =
+        |other: &Value| other.get("name") == instance.get("name") && other.get("channel").is_none();
+    match instances.iter_mut().find(|other| same_instance(other)) {
+        Some(other) => *other = instance,
+        None => instances.push(instance),
+    }
+    std::fs::write(spec_path, serde_json::to_string_pretty(&spec)? + "\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_path() {
+        assert!(check_path("mods/sodium.jar").is_ok());
+        assert!(check_path("config/sodium/options.json").is_ok());
+        assert!(check_path("../mods/sodium.jar").is_err());
+        assert!(check_path("mods/../../sodium.jar").is_err());
+        assert!(check_path("/etc/passwd").is_err());
+        assert!(check_path("./mods/sodium.jar").is_err());
+        assert!(check_path("mods\\..\\sodium.jar").is_err());
+    }
+
+    #[test]
+    fn test_parse_mrpack() {
+        let index: MrpackIndex = serde_json::from_value(json!({
+            "name": "Pack",
+            "dependencies": { "minecraft": "1.21.1", "fabric-loader": "0.16.10" },
+            "files": [
+                {
+                    "path": "mods/sodium.jar",
+                    "hashes": { "sha1": "aa", "sha512": "bb" },
+                    "downloads": ["https://cdn.modrinth.com/sodium.jar"],
+                    "fileSize": 10
+                },
+                {
+                    "path": "mods/server.jar",
+                    "hashes": { "sha1": "cc" },
+                    "env": { "client": "unsupported", "server": "required" },
+                    "downloads": ["https://cdn.modrinth.com/server.jar"]
+                },
+                {
+                    "path": "mods/client.jar",
+                    "hashes": { "sha1": "dd" },
+                    "env": { "client": "required", "server": "unsupported" },
+                    "downloads": ["https://cdn.modrinth.com/client.jar"]
+                },
+                {
+                    "path": "mods/missing.jar",
+                    "hashes": { "sha1": "ee" },
+                    "downloads": []
+                }
+            ]
+        }))
+        .unwrap();
+        let pack = parse_mrpack(index).unwrap();
+        assert_eq!(pack.minecraft_version, "1.21.1");
+        assert_eq!(pack.loader_name, "fabric");
+        assert_eq!(pack.loader_version.as_deref(), Some("0.16.10"));
+
+        let paths: Vec<&str> = pack.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["mods/sodium.jar", "mods/client.jar"]);
+        assert_eq!(pack.files[0].sha512.as_deref(), Some("bb"));
+        assert_eq!(pack.files[0].size, Some(10));
+        assert!(!pack.files[0].client_only);
+        assert!(pack.files[1].client_only);
+
+        let parse = |value| parse_mrpack(serde_json::from_value(value).unwrap());
+        assert!(parse(json!({ "name": "Pack", "dependencies": {}, "files": [] })).is_err());
+        assert!(
+            parse(json!({
+                "name": "Pack",
+                "dependencies": { "minecraft": "1.21.1", "unknown-loader": "1.0" },
+                "files": []
+            }))
+            .is_err()
+        );
+        assert!(
+            parse(json!({
+                "name": "Pack",
+                "dependencies": { "minecraft": "1.21.1" },
+                "files": [{
+                    "path": "../mods/sodium.jar",
+                    "hashes": { "sha1": "aa" },
+                    "downloads": ["https://cdn.modrinth.com/sodium.jar"]
+                }]
+            }))
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_curseforge() {
+        let options = CurseforgeOptions {
+            api_url: DEFAULT_CURSEFORGE_API_URL.to_string(),
+            api_key: None,
+        };
+        let manifest = |mod_loaders, files| {
+            serde_json::from_value::<CurseforgeManifest>(json!({
+                "minecraft": { "version": "1.20.1", "modLoaders": mod_loaders },
+                "name": "Pack",
+                "files": files,
+                "overrides": "overrides"
+            }))
+            .unwrap()
+        };
+
+        let pack = parse_curseforge(
+            manifest(
+                json!([{ "id": "fabric-0.16.10" }, { "id": "forge-47.2.0", "primary": true }]),
+                json!([]),
+            ),
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(pack.minecraft_version, "1.20.1");
+        assert_eq!(pack.loader_name, "forge");
+        assert_eq!(pack.loader_version.as_deref(), Some("47.2.0"));
+        assert_eq!(pack.override_dirs, ["overrides"]);
+
+        let pack = parse_curseforge(manifest(json!([]), json!([])), &options)
+            .await
+            .unwrap();
+        assert_eq!(pack.loader_name, "vanilla");
+        assert!(pack.loader_version.is_none());
+
+        let error = parse_curseforge(manifest(json!([{ "id": "rift-1.0" }]), json!([])), &options)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<ImportError>(),
+            Some(ImportError::UnsupportedDependency(_))
+        ));
+
+        // files are resolved through the API
+        let error = parse_curseforge(
+            manifest(json!([]), json!([{ "projectID": 1, "fileID": 2 }])),
+            &options,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<ImportError>(),
+            Some(ImportError::MissingCurseforgeApiKey)
+        ));
+    }
+
+    #[test]
+    fn test_get_include_rules() {
+        let files = [PackFile {
+            path: "mods/sodium.jar".to_string(),
+            url: "https://cdn.modrinth.com/sodium.jar".to_string(),
+            sha1: "aa".to_string(),
+            sha512: None,
+            size: Some(10),
+            client_only: true,
+        }];
+        let override_names = BTreeSet::from(["config".to_string()]);
+        let rules = get_include_rules(override_names, &files);
+        assert_eq!(
+            Value::Array(rules),
+            json!([
+                { "path": "config" },
+                {
+                    "path": "mods",
+                    "downloads": [{
+                        "path": "sodium.jar",
+                        "url": "https://cdn.modrinth.com/sodium.jar",
+                        "sha1": "aa",
+                        "size": 10,
+                        "client_only": true
+                    }]
+                }
+            ])
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use shared::{
+    generate::extra::RemoteFile,
+    loader_generator::{
+        fabric::FabricVersionsMeta,
+        forge::{ForgeMavenMetadata, ForgePromotions, NeoforgeMavenMetadata},
+        quilt::QuiltVersionsMeta,
+    },
+    utils::VANILLA_MANIFEST_URL,
+};
+use tokio::fs;
+
+use crate::{
+    maven::{self, MavenFile},
+    modrinth::{DEFAULT_MODRINTH_API_URL, ModrinthClient, ModrinthFile, get_selector},
+    selector::{SelectorError, get_prefix, matches_prefix, select_from_list},
+    spec::Spec,
+};
+
+#[derive(Deserialize)]
+struct LatestVanillaVersions {
+    release: String,
+    snapshot: String,
+}
+
+#[derive(Deserialize)]
+struct VanillaVersion {
+    id: String,
+    #[serde(rename = "type")]
+    version_type: String,
+}
+
+// the parts of the mojang manifest that shared::VersionManifest doesn't have
+#[derive(Deserialize)]
+struct VanillaVersions {
+    latest: LatestVanillaVersions,
+    versions: Vec<VanillaVersion>,
+}
+
+impl VanillaVersions {
+    async fn fetch() -> anyhow::Result<Self> {
+        let client = Client::new();
+        Ok(client
+            .get(VANILLA_MANIFEST_URL)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LockedVersion {
+    // the version as written in the spec, `None` for the default loader version
+    selector: Option<String>,
+    version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LockedModrinthFile {
+    // what the latest compatible version was looked up for
+    minecraft_version: String,
+    loader_name: String,
+    #[serde(flatten)]
+    file: ModrinthFile,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LockedInstance {
+    minecraft_version: LockedVersion,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    loader_version: Option<LockedVersion>,
+    // mod selector ("project", "project@version" or "@version") -> resolved file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    modrinth: BTreeMap<String, LockedModrinthFile>,
+    // maven coordinates as written in the spec -> resolved artifact
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    maven: BTreeMap<String, MavenFile>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SpecLock {
+    // instance build name -> resolved versions
+    instances: BTreeMap<String, LockedInstance>,
+}
+
+pub fn get_lock_path(spec_path: &Path) -> PathBuf {
+    spec_path.with_extension("lock")
+}
+
+async fn resolve_minecraft_version(
+    selector: &str,
+    vanilla_versions: &mut Option<VanillaVersions>,
+) -> anyhow::Result<String> {
+    let prefix = get_prefix(selector);
+    if !matches!(selector, "latest-release" | "latest-snapshot") && prefix.is_none() {
+        return Ok(selector.to_string());
+    }
+
+    if vanilla_versions.is_none() {
+        *vanilla_versions = Some(VanillaVersions::fetch().await?);
+    }
+    let vanilla_versions = vanilla_versions.as_ref().unwrap();
+    let version = match selector {
+        "latest-release" => Some(vanilla_versions.latest.release.clone()),
+        "latest-snapshot" => Some(vanilla_versions.latest.snapshot.clone()),
+        // versions are listed newest first, ranges only match releases
+        _ => vanilla_versions
+            .versions
+            .iter()
+            .find(|version| {
+                version.version_type == "release"
+                    && prefix.is_some_and(|prefix| matches_prefix(&version.id, prefix))
+            })
+            .map(|version| version.id.clone()),
+    };
+    version.ok_or(
+        SelectorError::NoMatchingVersion("minecraft".to_string(), selector.to_string()).into(),
+    )
+}
+
+async fn resolve_loader_version(
+    loader_name: &str,
+    minecraft_version: &str,
+    selector: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    if let Some(selector) = selector
+        && !matches!(selector, "latest" | "recommended")
+        && get_prefix(selector).is_none()
+    {
+        return Ok(Some(selector.to_string()));
+    }
+
+    let no_match = || {
+        SelectorError::NoMatchingVersion(
+            loader_name.to_string(),
+            selector.unwrap_or("default").to_string(),
+        )
+    };
+
+    let version = match loader_name {
+        "fabric" => {
+            let meta = FabricVersionsMeta::fetch(minecraft_version).await?;
+            select_from_list(
+                meta.get_versions(),
+                meta.get_latest_version(),
+                selector,
+                loader_name,
+            )?
+        }
+
+        "quilt" => {
+            let meta = QuiltVersionsMeta::fetch(minecraft_version).await?;
+            select_from_list(
+                meta.get_versions(),
+                meta.get_latest_version(),
+                selector,
+                loader_name,
+            )?
+        }
+
+        "forge" => match selector.map(|selector| (selector, get_prefix(selector))) {
+            None => ForgePromotions::fetch()
+                .await?
+                .get_latest_version(minecraft_version, "recommended")
+                .ok_or_else(no_match)?,
+            Some((promotion @ ("recommended" | "latest"), _)) => ForgePromotions::fetch()
+                .await?
+                .get_latest_version(minecraft_version, promotion)
+                .ok_or_else(no_match)?,
+            Some((_, Some(prefix))) => ForgeMavenMetadata::fetch()
+                .await?
+                .get_matching_versions(minecraft_version)
+                .into_iter()
+                .find(|version| matches_prefix(version, prefix))
+                .ok_or_else(no_match)?,
+            Some((selector, None)) => selector.to_string(),
+        },
+
+        "neoforge" => match selector.map(|selector| (selector, get_prefix(selector))) {
+            None | Some(("latest", _)) => NeoforgeMavenMetadata::fetch()
+                .await?
+                .get_latest_matching_version(minecraft_version)
+                .ok_or_else(no_match)?,
+            Some(("recommended", _)) => {
+                return Err(SelectorError::NoRecommendedVersion(loader_name.to_string()).into());
+            }
+            Some((_, Some(prefix))) => NeoforgeMavenMetadata::fetch()
+                .await?
+                .get_latest_version_with_prefix(minecraft_version, prefix)
+                .ok_or_else(no_match)?,
+            Some((selector, None)) => selector.to_string(),
+        },
+
+        // vanilla has no loader, unknown loaders are reported by the build
+        _ => return Ok(selector.map(str::to_string)),
+    };
+    Ok(Some(version))
+}
+
+/// Replace version selectors in the spec with concrete versions, taken from the lock file
+/// unless `update` is set or the selector has changed since it was locked.
+/// Modrinth mods and maven artifacts of the include rules are resolved to the files to download
+/// the same way.
+pub async fn lock_versions(
+    spec: &mut Spec,
+    lock_path: &Path,
+    update: bool,
+    save: bool,
+) -> anyhow::Result<()> {
+    let old_lock: SpecLock = match fs::read(lock_path).await {
+        Ok(data) => serde_json::from_slice(&data)?,
+        Err(_) => SpecLock::default(),
+    };
+    let mut lock = SpecLock::default();
+    let mut vanilla_versions = None;
+    let modrinth_client = ModrinthClient::new(
+        spec.modrinth_api_url
+            .as_deref()
+            .unwrap_or(DEFAULT_MODRINTH_API_URL),
+    )?;
+
+    for instance in spec.instances.iter_mut() {
+        let build_name = instance.get_build_name();
+        let old_locked = old_lock
+            .instances
+            .get(&build_name)
+            .filter(|_| !update)
+            .cloned();
+
+        let minecraft_version = match &old_locked {
+            Some(locked)
+                if locked.minecraft_version.selector.as_ref()
+                    == Some(&instance.minecraft_version) =>
+            {
+                locked.minecraft_version.version.clone()
+            }
+            _ => {
+                let version =
+                    resolve_minecraft_version(&instance.minecraft_version, &mut vanilla_versions)
+                        .await?;
+                if version != instance.minecraft_version {
+                    info!(
+                        "Resolved minecraft version \"{}\" of {build_name} to {version}",
+                        instance.minecraft_version
+                    );
+                }
+                version
+            }
+        };
+
+        // the loader version depends on the minecraft version, so it is resolved again if that changed
+        let locked_loader_version = old_locked.as_ref().and_then(|locked| {
+            locked
+                .loader_version
+                .clone()
+                .filter(|loader_version| {
+                    locked.minecraft_version.version == minecraft_version
+                        && loader_version.selector == instance.loader_version
+                })
+                .map(|loader_version| loader_version.version)
+        });
+        let loader_version = match locked_loader_version {
+            Some(version) => Some(version),
+            None => {
+                let version = resolve_loader_version(
+                    &instance.loader_name,
+                    &minecraft_version,
+                    instance.loader_version.as_deref(),
+                )
+                .await?;
+                if let Some(version) = &version
+                    && instance.loader_version.as_ref() != Some(version)
+                {
+                    info!(
+                        "Resolved {} version \"{}\" of {build_name} to {version}",
+                        instance.loader_name,
+                        instance.loader_version.as_deref().unwrap_or("default")
+                    );
+                }
+                version
+            }
+        };
+
+        let mut modrinth = BTreeMap::new();
+        let mut maven = BTreeMap::new();
+        for rule in instance.include.iter_mut() {
+            for modrinth_mod in &rule.modrinth {
+                let selector = get_selector(modrinth_mod)?;
+                let locked_file = old_locked
+                    .as_ref()
+                    .and_then(|locked| locked.modrinth.get(&selector))
+                    .filter(|locked_file| {
+                        locked_file.minecraft_version == minecraft_version
+                            && locked_file.loader_name == instance.loader_name
+                    })
+                    .cloned();
+                let locked_file = match locked_file {
+                    Some(locked_file) => locked_file,
+                    None => {
+                        let file = modrinth_client
+                            .resolve(modrinth_mod, &minecraft_version, &instance.loader_name)
+                            .await?;
+                        info!(
+                            "Resolved Modrinth mod \"{selector}\" of {build_name} to {} ({})",
+                            file.filename, file.version_id
+                        );
+                        LockedModrinthFile {
+                            minecraft_version: minecraft_version.clone(),
+                            loader_name: instance.loader_name.clone(),
+                            file,
+                        }
+                    }
+                };
+
+                let file = &locked_file.file;
+                rule.remote_files.push(RemoteFile {
+                    filename: file.filename.clone(),
+                    url: file.url.clone(),
+                    sha1: file.sha1.clone(),
+                    sha512: Some(file.sha512.clone()),
+                    size: Some(file.size),
+                    rehost: true,
+                    client_only: false,
+                    path: PathBuf::new(),
+                });
+                modrinth.insert(selector, locked_file);
+            }
+
+            for artifact in &rule.maven {
+                let locked_file = old_locked
+                    .as_ref()
+                    .and_then(|locked| locked.maven.get(&artifact.coordinates))
+                    .filter(|locked_file| locked_file.repository == artifact.repository)
+                    .cloned();
+                let file = match locked_file {
+                    Some(file) => file,
+                    None => {
+                        let file = maven::resolve(artifact).await?;
+                        info!(
+                            "Resolved maven artifact \"{}\" of {build_name} to {}",
+                            artifact.coordinates, file.version
+                        );
+                        file
+                    }
+                };
+
+                rule.remote_files.push(RemoteFile {
+                    filename: file.filename.clone(),
+                    url: file.url.clone(),
+                    sha1: file.sha1.clone(),
+                    sha512: None,
+                    size: None,
+                    rehost: true,
+                    client_only: false,
+                    path: PathBuf::new(),
+                });
+                maven.insert(artifact.coordinates.clone(), file);
+            }
+        }
+
+        lock.instances.insert(
+            build_name,
+            LockedInstance {
+                minecraft_version: LockedVersion {
+                    selector: Some(instance.minecraft_version.clone()),
+                    version: minecraft_version.clone(),
+                },
+                loader_version: loader_version.clone().map(|version| LockedVersion {
+                    selector: instance.loader_version.clone(),
+                    version,
+                }),
+                modrinth,
+                maven,
+            },
+        );
+        instance.minecraft_version = minecraft_version;
+        instance.loader_version = loader_version;
+    }
+
+    if save {
+        fs::write(lock_path, serde_json::to_string_pretty(&lock)?).await?;
+    }
+    Ok(())
+}
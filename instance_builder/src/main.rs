@@ -1,12 +1,28 @@
+mod events;
+mod export;
 mod generate;
+mod import;
+mod lock;
+mod maven;
+mod modrinth;
 mod progress;
+mod publish;
+mod s3;
+mod selector;
 mod spec;
+mod templates;
 mod utils;
 
-use clap::{Arg, Command};
-use shared::logs::setup_logger;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use events::Event;
+use lock::{get_lock_path, lock_versions};
+use shared::logs::setup_logger_with_hook;
+use shared::signature;
 use spec::Spec;
 use std::collections::HashSet;
+use std::io::Write as _;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt as _;
 use std::path::{Path, PathBuf};
 use tokio::runtime::Runtime;
 
@@ -28,13 +44,8 @@ pub fn get_logs_path(logs_dir: &Path) -> PathBuf {
     logs_dir.join(LOGS_FILENAME)
 }
 
-fn main() -> anyhow::Result<()> {
-    unsafe {
-        std::env::set_var("RUST_LIB_BACKTRACE", "1");
-    }
-
-    let matches = Command::new("generate-instance")
-        .about("Generates instances based on a specification file")
+fn spec_args(command: Command, default_output_dir: &'static str) -> Command {
+    command
         .arg(
             Arg::new("spec_file")
                 .help("Path to the specification file")
@@ -45,13 +56,34 @@ fn main() -> anyhow::Result<()> {
         .arg(
             Arg::new("output_dir")
                 .help("Output directory")
-                .default_value("./generated"),
+                .default_value(default_output_dir),
         )
         .arg(
             Arg::new("work_dir")
                 .help("Working directory")
                 .default_value("./workdir"),
         )
+        .arg(
+            Arg::new("progress")
+                .help("How to report progress")
+                .long("progress")
+                .value_parser(["terminal", "json"])
+                .default_value("terminal"),
+        )
+}
+
+// commands that resolve the versions of the spec
+fn locked_spec_args(command: Command, default_output_dir: &'static str) -> Command {
+    spec_args(command, default_output_dir).arg(
+        Arg::new("update")
+            .help("Resolve version selectors again instead of using the lock file")
+            .long("update")
+            .action(ArgAction::SetTrue),
+    )
+}
+
+fn build_args(command: Command) -> Command {
+    locked_spec_args(command, "./generated")
         .arg(
             Arg::new("delete_remote_instances")
                 .help("Comma-separated remote instance names to delete from fetched manifest")
@@ -61,8 +93,253 @@ fn main() -> anyhow::Result<()> {
                 .value_delimiter(',')
                 .value_name("NAME"),
         )
+        .arg(
+            Arg::new("jobs")
+                .help("Number of instances to generate in parallel")
+                .long("jobs")
+                .short('j')
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1"),
+        )
+}
+
+enum Mode {
+    Generate,
+    Plan { json: bool },
+    Server,
+    Publish { delete_orphans: bool },
+}
+
+fn keygen(key_path: &Path) -> anyhow::Result<()> {
+    if key_path.exists() {
+        return Err(anyhow::Error::msg("The key file already exists"));
+    }
+    let key = signature::generate_signing_key();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // the key is a secret, only its owner may read it
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(key_path)?;
+    file.write_all(signature::encode_signing_key(&key).as_bytes())?;
+    println!("Public key: {}", signature::get_public_key(&key));
+    Ok(())
+}
+
+fn import(matches: &ArgMatches) -> anyhow::Result<()> {
+    let archive = matches.get_one::<PathBuf>("archive").unwrap();
+    let include_from = match matches.get_one::<String>("include_from") {
+        Some(include_from) => PathBuf::from(include_from),
+        None => Path::new(".").join(archive.file_stem().unwrap()),
+    };
+    let curseforge = import::CurseforgeOptions {
+        api_url: matches
+            .get_one::<String>("curseforge_api_url")
+            .unwrap()
+            .clone(),
+        api_key: matches.get_one::<String>("curseforge_api_key").cloned(),
+    };
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let rt = Runtime::new().unwrap();
+    let instance = rt.block_on(import::import_modpack(
+        archive,
+        &include_from,
+        matches.get_one::<String>("name").map(String::as_str),
+        &curseforge,
+    ))?;
+    match matches.get_one::<PathBuf>("spec_file") {
+        Some(spec_file) => {
+            import::add_to_spec(spec_file, instance)?;
+            println!("Added the instance to {}", spec_file.display());
+        }
+        None => println!("{}", serde_json::to_string_pretty(&instance)?),
+    }
+    Ok(())
+}
+
+fn export(matches: &ArgMatches) -> anyhow::Result<()> {
+    let name = matches.get_one::<String>("name").unwrap();
+    let output_dir = PathBuf::from(matches.get_one::<String>("output_dir").unwrap());
+    let archive = match matches.get_one::<String>("archive") {
+        Some(archive) => PathBuf::from(archive),
+        None => PathBuf::from(format!("{name}.mrpack")),
+    };
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(export::export_instance(
+        &output_dir,
+        name,
+        matches.get_one::<String>("channel").map(String::as_str),
+        matches.get_one::<u64>("build").copied(),
+        &archive,
+        matches.get_flag("embed_files"),
+    ))
+}
+
+fn main() -> anyhow::Result<()> {
+    unsafe {
+        std::env::set_var("RUST_LIB_BACKTRACE", "1");
+    }
+
+    let matches = build_args(Command::new("generate-instance"))
+        .about("Generates instances based on a specification file")
+        .arg(
+            Arg::new("signing_key")
+                .help("Path to an Ed25519 key used to sign the version manifest")
+                .long("signing-key")
+                .value_name("PATH")
+                .value_parser(parse_path),
+        )
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            build_args(Command::new("plan"))
+                .about(
+                    "Shows what a build would change in the output directory without changing it",
+                )
+                .arg(
+                    Arg::new("json")
+                        .help("Print the plan as JSON")
+                        .long("json")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            locked_spec_args(Command::new("server"), "./servers")
+                .about("Generates a dedicated server for each instance"),
+        )
+        .subcommand(
+            spec_args(Command::new("publish"), "./generated")
+                .about("Uploads new and changed files of the output directory to S3-compatible storage")
+                .arg(
+                    Arg::new("delete_orphans")
+                        .help("Delete objects under the prefix that are not in the output directory")
+                        .long("delete-orphans")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Imports a Modrinth (.mrpack) or CurseForge modpack as an instance")
+                .arg(
+                    Arg::new("archive")
+                        .help("Path to the modpack archive")
+                        .required(true)
+                        .value_parser(parse_path),
+                )
+                .arg(
+                    Arg::new("spec_file")
+                        .help("Add the instance to this specification file instead of printing it")
+                        .short('s')
+                        .value_parser(parse_path),
+                )
+                .arg(
+                    Arg::new("include_from")
+                        .help("Where to extract the files of the modpack, defaults to ./<archive name>")
+                        .long("include-from")
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::new("name")
+                        .help("Instance name, defaults to the name of the modpack")
+                        .long("name"),
+                )
+                .arg(
+                    Arg::new("curseforge_api_key")
+                        .help("CurseForge API key, needed for CurseForge modpacks")
+                        .long("curseforge-api-key")
+                        .env("CURSEFORGE_API_KEY")
+                        .hide_env_values(true),
+                )
+                .arg(
+                    Arg::new("curseforge_api_url")
+                        .help("Base URL of the CurseForge API")
+                        .long("curseforge-api-url")
+                        .default_value(import::DEFAULT_CURSEFORGE_API_URL),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Exports a built instance as a Modrinth modpack (.mrpack)")
+                .arg(
+                    Arg::new("name")
+                        .help("Name of the instance")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output_dir")
+                        .help("Output directory of the build")
+                        .default_value("./generated"),
+                )
+                .arg(
+                    Arg::new("archive")
+                        .help("Where to write the modpack, defaults to ./<name>.mrpack")
+                        .long("output")
+                        .short('o')
+                        .value_name("PATH"),
+                )
+                .arg(
+                    Arg::new("channel")
+                        .help("Channel of the instance, the default channel if not set")
+                        .long("channel"),
+                )
+                .arg(
+                    Arg::new("build")
+                        .help("Export an older build, identified by its build timestamp")
+                        .long("build")
+                        .value_name("TIMESTAMP")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("embed_files")
+                        .help("Pack included files into the archive instead of linking to the download server")
+                        .long("embed-files")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("keygen")
+                .about("Generates a key for signing the version manifest")
+                .arg(
+                    Arg::new("key_file")
+                        .help("Where to write the secret key")
+                        .required(true),
+                ),
+        )
         .get_matches();
 
+    let (matches, mode) = match matches.subcommand() {
+        Some(("plan", plan_matches)) => (
+            plan_matches,
+            Mode::Plan {
+                json: plan_matches.get_flag("json"),
+            },
+        ),
+        Some(("server", server_matches)) => (server_matches, Mode::Server),
+        Some(("publish", publish_matches)) => (
+            publish_matches,
+            Mode::Publish {
+                delete_orphans: publish_matches.get_flag("delete_orphans"),
+            },
+        ),
+        Some(("import", import_matches)) => return import(import_matches),
+        Some(("export", export_matches)) => return export(export_matches),
+        Some(("keygen", keygen_matches)) => {
+            let key_file = keygen_matches.get_one::<String>("key_file").unwrap();
+            return keygen(Path::new(key_file));
+        }
+        _ => (&matches, Mode::Generate),
+    };
+
     let spec_file = matches.get_one::<PathBuf>("spec_file").unwrap();
     let output_dir = matches.get_one::<String>("output_dir").unwrap();
     let output_dir = PathBuf::from(output_dir);
@@ -73,13 +350,89 @@ fn main() -> anyhow::Result<()> {
     let output_dir_path = output_dir.clone();
     let work_dir_path = work_dir.clone();
 
-    setup_logger(&get_logs_path(&work_dir));
+    if matches.get_one::<String>("progress").map(String::as_str) == Some("json") {
+        events::enable_json();
+    }
+    setup_logger_with_hook(&get_logs_path(&work_dir), events::log_hook);
+
+    let result = run(
+        matches,
+        mode,
+        spec_file_path,
+        output_dir_path,
+        work_dir_path,
+    );
+    events::emit(Event::Finished {
+        error: result.as_ref().err().map(|e| format!("{e:#}")),
+    });
+    result
+}
 
+fn run(
+    matches: &ArgMatches,
+    mode: Mode,
+    spec_file_path: PathBuf,
+    output_dir_path: PathBuf,
+    work_dir_path: PathBuf,
+) -> anyhow::Result<()> {
     let rt = Runtime::new().unwrap();
-    let spec = rt.block_on(Spec::from_file(&spec_file_path))?;
+    let mut spec = rt.block_on(Spec::from_file(&spec_file_path))?;
+    // publishing only uploads what is already built, versions don't matter there
+    if let Mode::Publish { delete_orphans } = mode {
+        return rt.block_on(spec.publish(&output_dir_path, &work_dir_path, delete_orphans));
+    }
+    // plan doesn't change anything, including the lock
+    let save_lock = !matches!(mode, Mode::Plan { .. });
+    rt.block_on(lock_versions(
+        &mut spec,
+        &get_lock_path(&spec_file_path),
+        matches.get_flag("update"),
+        save_lock,
+    ))?;
     let delete_remote_set: Option<HashSet<String>> = matches
-        .get_many::<String>("delete_remote_instances")
+        .try_get_many::<String>("delete_remote_instances")
+        .ok()
+        .flatten()
         .map(|vals| vals.map(|s| s.to_string()).collect());
 
-    rt.block_on(spec.generate(&output_dir_path, &work_dir_path, delete_remote_set.as_ref()))
+    let jobs = matches
+        .try_get_one::<usize>("jobs")
+        .ok()
+        .flatten()
+        .copied()
+        .unwrap_or(1);
+
+    match mode {
+        Mode::Plan { json } => {
+            let plan = rt.block_on(spec.plan(
+                &output_dir_path,
+                &work_dir_path,
+                delete_remote_set.as_ref(),
+                jobs,
+            ))?;
+            if events::is_json() {
+                events::emit(Event::Plan { plan: &plan });
+            } else if json {
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else {
+                plan.print();
+            }
+            Ok(())
+        }
+        Mode::Publish { .. } => unreachable!(),
+        Mode::Server => rt.block_on(spec.generate_servers(&output_dir_path, &work_dir_path)),
+        Mode::Generate => {
+            let signing_key = match matches.get_one::<PathBuf>("signing_key") {
+                Some(path) => Some(rt.block_on(signature::read_signing_key(path))?),
+                None => None,
+            };
+            rt.block_on(spec.generate(
+                &output_dir_path,
+                &work_dir_path,
+                delete_remote_set.as_ref(),
+                signing_key.as_ref(),
+                jobs,
+            ))
+        }
+    }
 }
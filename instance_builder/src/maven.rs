@@ -0,0 +1,93 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use shared::{
+    generate::extra::MavenArtifact,
+    maven::{MavenCoordinates, MavenMetadata},
+};
+
+use crate::selector::{SelectorError, get_prefix, matches_prefix};
+
+#[derive(thiserror::Error, Debug)]
+pub enum MavenArtifactError {
+    #[error("Bad checksum file {0}")]
+    BadChecksum(String),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MavenFile {
+    pub repository: String,
+    pub version: String,
+    pub filename: String,
+    pub url: String,
+    pub sha1: String,
+}
+
+// the version in the coordinates can be "latest", "release" or a range like "1.2.*"
+async fn resolve_version(
+    repository: &str,
+    coordinates: &MavenCoordinates,
+) -> anyhow::Result<String> {
+    let selector = coordinates.version.as_str();
+    let prefix = get_prefix(selector);
+    if !matches!(selector, "latest" | "release") && prefix.is_none() {
+        return Ok(selector.to_string());
+    }
+
+    let metadata = MavenMetadata::fetch(repository, coordinates).await?;
+    let version = match selector {
+        "latest" => metadata
+            .versioning
+            .latest
+            .clone()
+            .or_else(|| metadata.get_versions().last().cloned()),
+        "release" => metadata.versioning.release.clone(),
+        _ => metadata
+            .get_versions()
+            .iter()
+            .rev()
+            .find(|version| prefix.is_some_and(|prefix| matches_prefix(version, prefix)))
+            .cloned(),
+    };
+    version.ok_or_else(|| {
+        SelectorError::NoMatchingVersion(
+            format!("{}:{}", coordinates.group, coordinates.artifact),
+            selector.to_string(),
+        )
+        .into()
+    })
+}
+
+pub async fn resolve(artifact: &MavenArtifact) -> anyhow::Result<MavenFile> {
+    let coordinates = MavenCoordinates::parse(&artifact.coordinates)?;
+    let version = resolve_version(&artifact.repository, &coordinates).await?;
+    let coordinates = coordinates.with_version(&version);
+    let url = format!(
+        "{}/{}",
+        artifact.repository.trim_end_matches('/'),
+        coordinates.get_path()
+    );
+
+    // some repositories put the file name after the hash
+    let checksum_url = format!("{url}.sha1");
+    let checksum = Client::new()
+        .get(&checksum_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let sha1 = checksum
+        .split_whitespace()
+        .next()
+        .filter(|sha1| sha1.len() == 40 && sha1.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or(MavenArtifactError::BadChecksum(checksum_url))?
+        .to_lowercase();
+
+    Ok(MavenFile {
+        repository: artifact.repository.clone(),
+        version,
+        filename: coordinates.get_filename(),
+        url,
+        sha1,
+    })
+}
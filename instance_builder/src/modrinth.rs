@@ -0,0 +1,188 @@
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use shared::generate::extra::ModrinthMod;
+
+pub const DEFAULT_MODRINTH_API_URL: &str = "https://api.modrinth.com/v2";
+
+// modrinth asks api clients to identify themselves
+const USER_AGENT: &str = concat!(
+    "potato_launcher/instance_builder/",
+    env!("CARGO_PKG_VERSION")
+);
+
+#[derive(thiserror::Error, Debug)]
+pub enum ModrinthError {
+    #[error("Modrinth mod needs a project or a version")]
+    NoSelector,
+    #[error("Modrinth version \"{0}\" not found")]
+    NotFound(String),
+    #[error("No version of Modrinth project \"{0}\" is compatible with minecraft {1} ({2})")]
+    NoCompatibleVersion(String, String, String),
+    #[error("Modrinth version \"{0}\" has no files")]
+    NoFiles(String),
+}
+
+#[derive(Deserialize)]
+struct FileHashes {
+    sha1: String,
+    sha512: String,
+}
+
+#[derive(Deserialize)]
+struct VersionFile {
+    hashes: FileHashes,
+    url: String,
+    filename: String,
+    primary: bool,
+    size: u64,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    id: String,
+    project_id: String,
+    version_number: String,
+    files: Vec<VersionFile>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ModrinthFile {
+    pub project_id: String,
+    pub version_id: String,
+    pub version_number: String,
+    pub filename: String,
+    pub url: String,
+    pub sha1: String,
+    pub sha512: String,
+    pub size: u64,
+}
+
+// the key of the mod in the lock file
+pub fn get_selector(modrinth_mod: &ModrinthMod) -> anyhow::Result<String> {
+    match (&modrinth_mod.project, &modrinth_mod.version) {
+        (Some(project), None) => Ok(project.clone()),
+        (Some(project), Some(version)) => Ok(format!("{project}@{version}")),
+        (None, Some(version)) => Ok(format!("@{version}")),
+        (None, None) => Err(ModrinthError::NoSelector.into()),
+    }
+}
+
+// modrinth loader names of the mods that run on the loader
+fn get_compatible_loaders(loader_name: &str) -> Vec<&str> {
+    match loader_name {
+        "quilt" => vec!["quilt", "fabric"],
+        "vanilla" => vec![],
+        loader_name => vec![loader_name],
+    }
+}
+
+pub struct ModrinthClient {
+    client: Client,
+    api_url: String,
+}
+
+impl ModrinthClient {
+    pub fn new(api_url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: Client::builder().user_agent(USER_AGENT).build()?,
+            api_url: api_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    // returns `None` on 404
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<Option<T>> {
+        let response = self
+            .client
+            .get(format!("{}{path}", self.api_url))
+            .query(query)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    async fn get_version(&self, modrinth_mod: &ModrinthMod) -> anyhow::Result<Version> {
+        let version = modrinth_mod.version.as_deref().unwrap_or_default();
+        let path = match &modrinth_mod.project {
+            // the version can also be a version number here
+            Some(project) => format!("/project/{project}/version/{version}"),
+            None => format!("/version/{version}"),
+        };
+        self.get(&path, &[])
+            .await?
+            .ok_or_else(|| ModrinthError::NotFound(version.to_string()).into())
+    }
+
+    async fn get_latest_version(
+        &self,
+        project: &str,
+        minecraft_version: &str,
+        loader_name: &str,
+    ) -> anyhow::Result<Version> {
+        let mut query = vec![(
+            "game_versions",
+            serde_json::to_string(&[minecraft_version])?,
+        )];
+        let loaders = get_compatible_loaders(loader_name);
+        if !loaders.is_empty() {
+            query.push(("loaders", serde_json::to_string(&loaders)?));
+        }
+        // versions are listed newest first
+        let versions: Vec<Version> = self
+            .get(&format!("/project/{project}/version"), &query)
+            .await?
+            .ok_or_else(|| ModrinthError::NotFound(project.to_string()))?;
+        versions.into_iter().next().ok_or_else(|| {
+            ModrinthError::NoCompatibleVersion(
+                project.to_string(),
+                minecraft_version.to_string(),
+                loader_name.to_string(),
+            )
+            .into()
+        })
+    }
+
+    pub async fn resolve(
+        &self,
+        modrinth_mod: &ModrinthMod,
+        minecraft_version: &str,
+        loader_name: &str,
+    ) -> anyhow::Result<ModrinthFile> {
+        let version = match (&modrinth_mod.project, &modrinth_mod.version) {
+            (Some(project), None) => {
+                self.get_latest_version(project, minecraft_version, loader_name)
+                    .await?
+            }
+            (_, Some(_)) => self.get_version(modrinth_mod).await?,
+            (None, None) => return Err(ModrinthError::NoSelector.into()),
+        };
+
+        let Version {
+            id,
+            project_id,
+            version_number,
+            files,
+        } = version;
+        let position = files.iter().position(|file| file.primary).unwrap_or(0);
+        let file = files
+            .into_iter()
+            .nth(position)
+            .ok_or_else(|| ModrinthError::NoFiles(id.clone()))?;
+        Ok(ModrinthFile {
+            project_id,
+            version_id: id,
+            version_number,
+            filename: file.filename,
+            url: file.url,
+            sha1: file.hashes.sha1,
+            sha512: file.hashes.sha512,
+            size: file.size,
+        })
+    }
+}
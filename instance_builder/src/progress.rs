@@ -1,44 +1,140 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
 use shared::progress::{ProgressBar, Unit};
 
-pub struct TerminalProgressBar {
-    bar: indicatif::ProgressBar,
+use crate::events::{self, Event};
+
+const COUNT_TEMPLATE: &str = "{msg} {bar:40.cyan/blue} {pos}/{len}";
+// indicatif formats bytes on its own, so the unit size is not needed here
+const BYTES_TEMPLATE: &str =
+    "{msg} {bar:40.cyan/blue} {bytes}/{total_bytes} ({binary_bytes_per_sec}, {eta} left)";
+
+// downloads call inc for every file, so progress events are sent at most this often
+const JSON_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct JsonProgress {
+    instance: Option<String>,
+    message: Mutex<String>,
+    position: AtomicU64,
+    length: AtomicU64,
+    bytes: AtomicBool,
+    last_emit: Mutex<Option<Instant>>,
+}
+
+impl JsonProgress {
+    fn emit(&self, force: bool) {
+        {
+            let mut last_emit = self.last_emit.lock().unwrap();
+            if !force && last_emit.is_some_and(|last| last.elapsed() < JSON_PROGRESS_INTERVAL) {
+                return;
+            }
+            *last_emit = Some(Instant::now());
+        }
+        events::emit(Event::Progress {
+            instance: self.instance.clone(),
+            message: &self.message.lock().unwrap(),
+            position: self.position.load(Ordering::Relaxed),
+            length: self.length.load(Ordering::Relaxed),
+            unit: self.bytes.load(Ordering::Relaxed).then_some("bytes"),
+        });
+    }
 }
 
-impl TerminalProgressBar {
+/// Draws a progress bar in the terminal, or reports progress events in the JSON event mode.
+pub enum BuilderProgressBar {
+    Terminal(indicatif::ProgressBar),
+    Json(JsonProgress),
+}
+
+fn style(template: &str) -> indicatif::ProgressStyle {
+    indicatif::ProgressStyle::default_bar()
+        .template(template)
+        .unwrap()
+}
+
+impl BuilderProgressBar {
     pub fn new() -> Self {
+        if events::is_json() {
+            return Self::Json(JsonProgress {
+                instance: events::current_instance(),
+                message: Mutex::new(String::new()),
+                position: AtomicU64::new(0),
+                length: AtomicU64::new(0),
+                bytes: AtomicBool::new(false),
+                last_emit: Mutex::new(None),
+            });
+        }
         let bar = indicatif::ProgressBar::new(0);
-        bar.set_style(
-            indicatif::ProgressStyle::default_bar()
-                .template("{msg} {bar:40.cyan/blue} {pos}/{len}")
-                .unwrap(),
-        );
-        Self { bar }
+        bar.set_style(style(COUNT_TEMPLATE));
+        Self::Terminal(bar)
     }
 }
 
-impl ProgressBar<&str> for TerminalProgressBar {
+impl ProgressBar<&str> for BuilderProgressBar {
     fn set_message(&self, message: &str) {
-        self.bar.set_message(message.to_string());
+        match self {
+            Self::Terminal(bar) => bar.set_message(message.to_string()),
+            Self::Json(progress) => {
+                *progress.message.lock().unwrap() = message.to_string();
+                progress.emit(true);
+            }
+        }
     }
 
     fn set_length(&self, length: u64) {
-        self.bar.set_length(length);
+        match self {
+            Self::Terminal(bar) => bar.set_length(length),
+            Self::Json(progress) => progress.length.store(length, Ordering::Relaxed),
+        }
     }
 
     fn inc(&self, amount: u64) {
-        self.bar.inc(amount);
+        match self {
+            Self::Terminal(bar) => bar.inc(amount),
+            Self::Json(progress) => {
+                progress.position.fetch_add(amount, Ordering::Relaxed);
+                progress.emit(false);
+            }
+        }
     }
 
     fn finish(&self) {
-        self.bar.finish();
+        match self {
+            Self::Terminal(bar) => bar.finish(),
+            Self::Json(progress) => progress.emit(true),
+        }
     }
 
     fn reset(&self) {
-        self.bar.set_length(0);
-        self.bar.set_position(0);
+        match self {
+            Self::Terminal(bar) => {
+                bar.set_length(0);
+                bar.set_position(0);
+            }
+            Self::Json(progress) => {
+                progress.length.store(0, Ordering::Relaxed);
+                progress.position.store(0, Ordering::Relaxed);
+            }
+        }
     }
 
     fn set_unit(&self, _: Unit) {
-        unimplemented!();
+        match self {
+            Self::Terminal(bar) => bar.set_style(style(BYTES_TEMPLATE)),
+            Self::Json(progress) => progress.bytes.store(true, Ordering::Relaxed),
+        }
+    }
+
+    fn clear_unit(&self) {
+        match self {
+            Self::Terminal(bar) => bar.set_style(style(COUNT_TEMPLATE)),
+            Self::Json(progress) => progress.bytes.store(false, Ordering::Relaxed),
+        }
     }
 }
@@ -0,0 +1,287 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use shared::{
+    files,
+    hash_cache::HashCache,
+    progress::{self, ProgressBar as _, run_tasks_with_progress},
+    signature::get_signature_path,
+};
+
+use crate::{
+    events::{self, Event},
+    progress::BuilderProgressBar,
+    s3::{Payload, S3Client, S3Config},
+    spec::get_manifest_path,
+};
+
+// sha1 of every published file, kept in the bucket next to the files
+const INDEX_FILENAME: &str = "publish-index.json";
+const DEFAULT_REGION: &str = "us-east-1";
+const UPLOAD_CONCURRENCY: usize = 8;
+// the manifest is fetched on every launcher start and has to be fresh
+const MANIFEST_CACHE_CONTROL: &str = "no-cache";
+
+#[derive(thiserror::Error, Debug)]
+pub enum PublishError {
+    #[error("Publish option \"{0}\" is not set, set it in the spec or with {1}")]
+    MissingOption(&'static str, &'static str),
+}
+
+#[derive(Deserialize, Default)]
+pub struct PublishConfig {
+    pub endpoint: Option<String>,
+    pub bucket: Option<String>,
+    pub prefix: Option<String>,
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, PartialEq)]
+struct PublishIndex {
+    // path relative to the prefix -> sha1
+    files: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Copy, Default)]
+pub struct PublishStats {
+    pub total_files: usize,
+    pub uploaded_files: usize,
+    pub deleted_files: usize,
+    pub orphaned_files: usize,
+}
+
+// environment variables take precedence, so secrets don't have to be in the spec
+fn get_option(value: &Option<String>, env_names: &[&str]) -> Option<String> {
+    env_names
+        .iter()
+        .find_map(|env_name| std::env::var(env_name).ok())
+        .or_else(|| value.clone())
+        .filter(|value| !value.is_empty())
+}
+
+fn get_required_option(
+    value: &Option<String>,
+    name: &'static str,
+    env_names: &[&'static str],
+) -> anyhow::Result<String> {
+    get_option(value, env_names)
+        .ok_or_else(|| PublishError::MissingOption(name, env_names[0]).into())
+}
+
+impl PublishConfig {
+    fn resolve(&self) -> anyhow::Result<(S3Config, String)> {
+        let config = S3Config {
+            endpoint: get_required_option(&self.endpoint, "endpoint", &["PUBLISH_ENDPOINT"])?,
+            bucket: get_required_option(&self.bucket, "bucket", &["PUBLISH_BUCKET"])?,
+            region: get_option(&self.region, &["PUBLISH_REGION"])
+                .unwrap_or(DEFAULT_REGION.to_string()),
+            access_key_id: get_required_option(
+                &self.access_key_id,
+                "access_key_id",
+                &["PUBLISH_ACCESS_KEY_ID", "AWS_ACCESS_KEY_ID"],
+            )?,
+            secret_access_key: get_required_option(
+                &self.secret_access_key,
+                "secret_access_key",
+                &["PUBLISH_SECRET_ACCESS_KEY", "AWS_SECRET_ACCESS_KEY"],
+            )?,
+        };
+        let prefix = get_option(&self.prefix, &["PUBLISH_PREFIX"]).unwrap_or_default();
+        Ok((config, prefix.trim_matches('/').to_string()))
+    }
+}
+
+fn get_key(prefix: &str, rel_path: &str) -> String {
+    if prefix.is_empty() {
+        rel_path.to_string()
+    } else {
+        format!("{prefix}/{rel_path}")
+    }
+}
+
+// keys always use forward slashes
+fn to_rel_path(path: &Path, base_dir: &Path) -> anyhow::Result<String> {
+    Ok(path
+        .strip_prefix(base_dir)?
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+async fn upload_file(
+    client: &S3Client,
+    output_dir: &Path,
+    prefix: &str,
+    rel_path: &str,
+    cache_control: Option<&str>,
+) -> anyhow::Result<()> {
+    let payload = Payload::from_file(&output_dir.join(rel_path)).await?;
+    client
+        .put_object(&get_key(prefix, rel_path), payload, cache_control)
+        .await
+}
+
+/// Upload new and changed files of `output_dir` to the bucket, with the version manifest last,
+/// so the launcher never sees a manifest that points to files that aren't uploaded yet.
+pub async fn publish(
+    config: &PublishConfig,
+    output_dir: &Path,
+    hash_cache: &mut HashCache,
+    delete_orphans: bool,
+) -> anyhow::Result<PublishStats> {
+    let (s3_config, prefix) = config.resolve()?;
+    info!(
+        "Publishing {} to bucket {} at {}",
+        output_dir.display(),
+        s3_config.bucket,
+        s3_config.endpoint
+    );
+    let client = S3Client::new(s3_config)?;
+
+    let local_files = files::get_files_in_dir(output_dir)?;
+    let progress_bar = Arc::new(BuilderProgressBar::new());
+    progress_bar.set_message("Hashing files");
+    let hashes = hash_cache
+        .hash_files(local_files.clone(), false, progress_bar.clone())
+        .await?;
+    progress_bar.finish();
+
+    let mut index = PublishIndex::default();
+    for (path, sha1) in local_files.iter().zip(hashes) {
+        index.files.insert(to_rel_path(path, output_dir)?, sha1);
+    }
+
+    info!("Fetching remote file list");
+    let list_prefix = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{prefix}/")
+    };
+    let remote_keys: HashSet<String> = client
+        .list_objects(&list_prefix)
+        .await?
+        .into_iter()
+        .collect();
+    let index_key = get_key(&prefix, INDEX_FILENAME);
+    let remote_index: PublishIndex = match client.get_object(&index_key).await? {
+        Some(data) => serde_json::from_slice(&data)?,
+        None => PublishIndex::default(),
+    };
+
+    // files that were deleted or changed by hand are uploaded again
+    let changed_files: Vec<&str> = index
+        .files
+        .iter()
+        .filter(|(rel_path, sha1)| {
+            remote_index.files.get(*rel_path) != Some(*sha1)
+                || !remote_keys.contains(&get_key(&prefix, rel_path))
+        })
+        .map(|(rel_path, _)| rel_path.as_str())
+        .collect();
+
+    let manifest_path = get_manifest_path(output_dir);
+    let manifest_files = [get_signature_path(&manifest_path), manifest_path]
+        .iter()
+        .map(|path| to_rel_path(path, output_dir))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let (last_files, first_files): (Vec<&str>, Vec<&str>) = changed_files
+        .into_iter()
+        .partition(|rel_path| manifest_files.iter().any(|file| file == rel_path));
+
+    events::stage("uploading");
+    info!("Uploading {} changed files", first_files.len());
+    progress_bar.reset();
+    progress_bar.set_message("Uploading files");
+    let tasks = first_files
+        .iter()
+        .map(|rel_path| upload_file(&client, output_dir, &prefix, rel_path, None));
+    run_tasks_with_progress(
+        tasks,
+        progress_bar.clone(),
+        first_files.len() as u64,
+        UPLOAD_CONCURRENCY,
+    )
+    .await?;
+    progress_bar.finish();
+
+    // the signature goes first, so the new manifest never comes with an outdated signature
+    for rel_path in manifest_files
+        .iter()
+        .filter(|file| last_files.contains(&file.as_str()))
+    {
+        info!("Uploading {rel_path}");
+        upload_file(
+            &client,
+            output_dir,
+            &prefix,
+            rel_path,
+            Some(MANIFEST_CACHE_CONTROL),
+        )
+        .await?;
+    }
+
+    if index != remote_index {
+        client
+            .put_object(
+                &index_key,
+                Payload::from_bytes(serde_json::to_vec(&index)?),
+                None,
+            )
+            .await?;
+    }
+
+    let published_keys: HashSet<String> = index
+        .files
+        .keys()
+        .map(|rel_path| get_key(&prefix, rel_path))
+        .collect();
+    let mut orphaned_keys: Vec<&String> = remote_keys
+        .iter()
+        .filter(|key| **key != index_key && !published_keys.contains(*key))
+        .collect();
+    orphaned_keys.sort();
+
+    let mut stats = PublishStats {
+        total_files: index.files.len(),
+        uploaded_files: first_files.len() + last_files.len(),
+        deleted_files: 0,
+        orphaned_files: orphaned_keys.len(),
+    };
+    if delete_orphans && !orphaned_keys.is_empty() {
+        events::stage("deleting_orphans");
+        info!("Deleting {} orphaned objects", orphaned_keys.len());
+        let tasks = orphaned_keys.iter().map(|key| client.delete_object(key));
+        run_tasks_with_progress(
+            tasks,
+            progress::no_progress_bar(),
+            orphaned_keys.len() as u64,
+            UPLOAD_CONCURRENCY,
+        )
+        .await?;
+        stats.deleted_files = orphaned_keys.len();
+        stats.orphaned_files = 0;
+    } else if !orphaned_keys.is_empty() {
+        info!(
+            "{} objects in the bucket don't belong to the output directory, use --delete-orphans to delete them",
+            orphaned_keys.len()
+        );
+    }
+
+    events::emit(Event::Published { stats });
+    info!(
+        "Published {} files (uploaded {}, deleted {})",
+        stats.total_files, stats.uploaded_files, stats.deleted_files
+    );
+    Ok(stats)
+}
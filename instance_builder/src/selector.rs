@@ -0,0 +1,77 @@
+#[derive(thiserror::Error, Debug)]
+pub enum SelectorError {
+    #[error("No {0} version matches \"{1}\"")]
+    NoMatchingVersion(String, String),
+    #[error("{0} has no recommended versions, use \"latest\" or a version range")]
+    NoRecommendedVersion(String),
+}
+
+// "47.2.*" matches "47.2", "47.2.0" and "47.2.10", but not "47.20.1"
+pub fn get_prefix(selector: &str) -> Option<&str> {
+    selector.strip_suffix('*')
+}
+
+pub fn matches_prefix(version: &str, prefix: &str) -> bool {
+    version.starts_with(prefix) || prefix.strip_suffix('.') == Some(version)
+}
+
+pub fn select_from_list(
+    versions: Vec<&str>,
+    latest: Option<&str>,
+    selector: Option<&str>,
+    loader_name: &str,
+) -> anyhow::Result<String> {
+    let version = match selector {
+        None | Some("latest") => latest,
+        Some("recommended") => {
+            return Err(SelectorError::NoRecommendedVersion(loader_name.to_string()).into());
+        }
+        Some(selector) => match get_prefix(selector) {
+            Some(prefix) => versions
+                .into_iter()
+                .find(|version| matches_prefix(version, prefix)),
+            None => Some(selector),
+        },
+    };
+    Ok(version
+        .ok_or(SelectorError::NoMatchingVersion(
+            loader_name.to_string(),
+            selector.unwrap_or("latest").to_string(),
+        ))?
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_prefix() {
+        let prefix = get_prefix("47.2.*").unwrap();
+        assert!(matches_prefix("47.2", prefix));
+        assert!(matches_prefix("47.2.0", prefix));
+        assert!(matches_prefix("47.2.10", prefix));
+        assert!(!matches_prefix("47.20.1", prefix));
+        assert!(get_prefix("47.2.0").is_none());
+    }
+
+    #[test]
+    fn test_select_from_list() {
+        let versions = || vec!["0.16.10", "0.16.9", "0.15.11"];
+        let select = |selector| select_from_list(versions(), Some("0.16.10"), selector, "fabric");
+
+        assert_eq!(select(None).unwrap(), "0.16.10");
+        assert_eq!(select(Some("latest")).unwrap(), "0.16.10");
+        assert_eq!(select(Some("0.15.*")).unwrap(), "0.15.11");
+        assert_eq!(select(Some("0.16.9")).unwrap(), "0.16.9");
+        assert!(select(Some("0.14.*")).is_err());
+
+        let error = select(Some("recommended")).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<SelectorError>(),
+            Some(SelectorError::NoRecommendedVersion(_))
+        ));
+
+        assert!(select_from_list(vec![], None, None, "quilt").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use futures::{StreamExt as _, TryStreamExt as _, stream};
 use log::{debug, error, info, warn};
 use serde::Deserialize;
 use std::{
@@ -5,34 +6,56 @@ use std::{
     path::{Path, PathBuf},
     sync::Arc,
 };
-use tokio::fs;
+use tokio::{fs, sync::Mutex};
 
 use shared::{
-    files::sync_mapping,
+    files::{
+        copy_mapping_cached, get_unmapped_files, plan_mapping, remove_empty_dirs,
+        sync_mapping_cached, write_atomic,
+    },
     generate::{
         extra::{ExtraMetadataGenerator, IncludeConfig, IncludeRule},
-        manifest::get_version_info,
+        manifest::{get_metadata_source_path, get_version_info},
     },
+    hash_cache::HashCache,
     loader_generator::{
         fabric::FabricGenerator,
         forge::{ForgeGenerator, Loader},
         generator::VersionGenerator,
+        quilt::QuiltGenerator,
         vanilla::VanillaGenerator,
     },
     paths::{
-        get_extra_metadata_path, get_metadata_path, get_minecraft_dir, get_versions_dir,
-        get_versions_extra_dir,
+        get_extra_metadata_path, get_hash_cache_path, get_metadata_path, get_rel_object_path,
+        get_versions_dir, get_versions_extra_dir,
     },
-    utils::{VANILLA_MANIFEST_URL, get_vanilla_version_info},
+    signature::{self, SigningKey, get_signature_path},
+    utils::{KeyedLocks, VANILLA_MANIFEST_URL, get_vanilla_version_info},
     version::{
-        asset_metadata::AssetsMetadata, extra_version_metadata::AuthBackend,
-        version_manifest::VersionManifest,
+        asset_metadata::AssetsMetadata,
+        extra_version_metadata::AuthBackend,
+        version_manifest::{VersionInfo, VersionManifest},
+        version_metadata::VersionMetadata,
     },
 };
 
 use crate::{
-    generate::{mapping::get_mapping, patch::replace_download_urls, sync::sync_version},
-    progress::TerminalProgressBar,
+    events::{self, Event},
+    generate::{
+        history::{BuildSnapshot, remove_unused_history_objects, update_history},
+        java::download_java_runtimes,
+        mapping::get_mapping,
+        objects::map_version_objects,
+        patch::replace_download_urls,
+        plan::BuildPlan,
+        remote::download_remote_files,
+        retention::Retention,
+        server::build_server,
+        sync::sync_version,
+    },
+    progress::BuilderProgressBar,
+    publish::{PublishConfig, publish},
+    templates::resolve_templates,
     utils::{exec_string_command, get_assets_dir, get_replaced_metadata_dir},
 };
 
@@ -40,6 +63,10 @@ fn vanilla() -> String {
     "vanilla".to_string()
 }
 
+fn default_grace_period_hours() -> u64 {
+    24
+}
+
 #[derive(Deserialize)]
 pub struct Instances {
     pub name: String,
@@ -61,6 +88,18 @@ pub struct Instances {
 
     pub exec_before: Option<String>,
     pub exec_after: Option<String>,
+
+    pub channel: Option<String>,
+}
+
+impl Instances {
+    // channels of one instance share the name, so they are generated under distinct ids
+    pub fn get_build_name(&self) -> String {
+        match &self.channel {
+            Some(channel) => format!("{} ({channel})", self.name),
+            None => self.name.clone(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -73,27 +112,200 @@ pub struct Spec {
 
     pub version_manifest_url: Option<String>,
 
+    // number of builds of each instance to keep published, 0 disables the history
+    #[serde(default)]
+    pub history_size: usize,
+
+    // how long files of replaced builds stay in output_dir
+    #[serde(default = "default_grace_period_hours")]
+    pub grace_period_hours: u64,
+
     pub instances: Vec<Instances>,
     pub exec_before_all: Option<String>,
     pub exec_after_all: Option<String>,
+
+    #[serde(default)]
+    pub publish: PublishConfig,
+
+    // defaults to the public Modrinth API
+    pub modrinth_api_url: Option<String>,
+
+    // "<os>-<arch>" platforms to publish java runtimes for, e.g. windows-x86_64,
+    // players on other platforms download java from Azul
+    #[serde(default)]
+    pub java_platforms: Vec<String>,
 }
 
 pub fn get_manifest_path(data_dir: &Path) -> PathBuf {
     data_dir.join("version_manifest.json")
 }
 
+// state shared by the instances built in parallel
+struct BuildContext<'a> {
+    vanilla_manifest: VersionManifest,
+    output_dir: &'a Path,
+    work_dir: &'a Path,
+    dry_run: bool,
+    // metadata id -> metadata with replaced urls
+    synced_metadata: Mutex<HashMap<String, PathBuf>>,
+    hash_cache: Mutex<HashCache>,
+    minecraft_version_locks: KeyedLocks,
+    // by metadata id and asset index, so shared libraries and assets are synced once
+    sync_locks: KeyedLocks,
+    // by path of a file downloaded into work_dir
+    download_locks: KeyedLocks,
+}
+
+struct InstanceResult {
+    version_info: VersionInfo,
+    // target -> source
+    mapping: HashMap<PathBuf, PathBuf>,
+    history_mapping: HashMap<PathBuf, PathBuf>,
+}
+
+// everything generated in work_dir that still has to be synced to output_dir
+struct BuildResult {
+    // target -> source
+    mapping: HashMap<PathBuf, PathBuf>,
+    version_manifest: VersionManifest,
+    deleted_remote_instances: Vec<String>,
+    hash_cache: HashCache,
+    // sources of the files of recorded builds
+    history_sources: HashSet<PathBuf>,
+}
+
 impl Spec {
     pub async fn from_file(path: &Path) -> anyhow::Result<Spec> {
         let content = fs::read_to_string(path).await?;
-        let spec = serde_json::from_str(&content)?;
-        Ok(spec)
+        let mut spec = serde_json::from_str(&content)?;
+        resolve_templates(&mut spec)?;
+        Ok(serde_json::from_value(spec)?)
     }
 
     pub async fn generate(
+        mut self,
+        output_dir: &Path,
+        work_dir: &Path,
+        delete_remote_instances: Option<&HashSet<String>>,
+        signing_key: Option<&SigningKey>,
+        jobs: usize,
+    ) -> anyhow::Result<()> {
+        let exec_after_all = self.exec_after_all.take();
+        let grace_period_hours = self.grace_period_hours;
+        let BuildResult {
+            mapping,
+            version_manifest,
+            mut hash_cache,
+            history_sources,
+            ..
+        } = self
+            .build(output_dir, work_dir, delete_remote_instances, false, jobs)
+            .await?;
+
+        // new files are copied next to the old ones, the manifest switches to them at once
+        info!("Syncing {} entries", mapping.len());
+        debug!("Sync mapping (target->source): {mapping:?}");
+        events::stage("syncing_output");
+        let mut stats = copy_mapping_cached(output_dir, &mapping, &mut hash_cache).await?;
+
+        let manifest_path = get_manifest_path(output_dir);
+        let signature_path = get_signature_path(&manifest_path);
+        let manifest_data = serde_json::to_string(&version_manifest)?;
+        if let Some(signing_key) = signing_key {
+            // metadata and extra metadata are covered through their hashes in the manifest
+            info!(
+                "Signing version manifest with public key {}",
+                signature::get_public_key(signing_key)
+            );
+            write_atomic(
+                &signature_path,
+                signature::sign(signing_key, manifest_data.as_bytes()),
+            )
+            .await?;
+        }
+        write_atomic(&manifest_path, &manifest_data).await?;
+
+        let mut unmapped_files = get_unmapped_files(output_dir, &mapping)?;
+        unmapped_files.retain(|path| path != &manifest_path && path != &signature_path);
+        let mut retention = Retention::load(work_dir, grace_period_hours).await;
+        let expired_files = retention.expire(output_dir, unmapped_files)?;
+        for path in &expired_files {
+            fs::remove_file(path).await?;
+        }
+        remove_empty_dirs(output_dir).await?;
+        retention.save().await?;
+        stats.deleted_files = expired_files.len();
+        remove_unused_history_objects(work_dir, &history_sources).await?;
+
+        events::emit(Event::Synced {
+            instance: None,
+            stats,
+        });
+        info!(
+            "Synced {} files (copied {}, deleted {}), hash cache hits: sync {}, total {}",
+            stats.total_files,
+            stats.copied_files,
+            stats.deleted_files,
+            stats.hash_cache,
+            hash_cache.stats()
+        );
+
+        hash_cache.prune();
+        hash_cache.save(&get_hash_cache_path(work_dir)).await?;
+
+        if let Some(command) = &exec_after_all {
+            events::stage("exec_after_all");
+            exec_string_command(command).await?;
+        }
+        Ok(())
+    }
+
+    // generate everything like `generate` does, then report the changes instead of applying them
+    pub async fn plan(
         self,
         output_dir: &Path,
         work_dir: &Path,
         delete_remote_instances: Option<&HashSet<String>>,
+        jobs: usize,
+    ) -> anyhow::Result<BuildPlan> {
+        let grace_period_hours = self.grace_period_hours;
+        let BuildResult {
+            mapping,
+            version_manifest,
+            deleted_remote_instances,
+            mut hash_cache,
+            ..
+        } = self
+            .build(output_dir, work_dir, delete_remote_instances, true, jobs)
+            .await?;
+
+        let manifest_path = get_manifest_path(output_dir);
+        let mut files = plan_mapping(output_dir, &mapping, &mut hash_cache).await?;
+        // the manifest is written after the sync, it is reported per instance below
+        let signature_path = get_signature_path(&manifest_path);
+        files
+            .deleted
+            .retain(|path| path != &manifest_path && path != &signature_path);
+        // the others are kept until their grace period is over
+        let mut retention = Retention::load(work_dir, grace_period_hours).await;
+        files.deleted = retention.expire(output_dir, files.deleted)?;
+
+        let old_manifest = VersionManifest::read_local_safe(&manifest_path).await;
+        let plan = BuildPlan::new(
+            output_dir,
+            files,
+            &old_manifest,
+            &version_manifest,
+            deleted_remote_instances,
+        );
+        Ok(plan)
+    }
+
+    /// Prepare a dedicated server for every instance in `output_dir/<instance name>`.
+    pub async fn generate_servers(
+        mut self,
+        output_dir: &Path,
+        work_dir: &Path,
     ) -> anyhow::Result<()> {
         if let Some(command) = &self.exec_before_all {
             exec_string_command(command).await?;
@@ -102,12 +314,88 @@ impl Spec {
         info!("Fetching version manifest");
         let vanilla_manifest = VersionManifest::fetch(VANILLA_MANIFEST_URL).await?;
 
+        let hash_cache_path = get_hash_cache_path(work_dir);
+        let mut hash_cache = HashCache::load(&hash_cache_path).await;
+
+        for version in self.instances.iter_mut() {
+            let build_name = version.get_build_name();
+            let job = async {
+                if let Some(command) = &version.exec_before {
+                    exec_string_command(command).await?;
+                }
+                download_remote_files(&mut version.include, work_dir, &mut hash_cache).await?;
+
+                info!("Generating server for {}", &build_name);
+                events::stage("generating");
+                let vanilla_version_info =
+                    get_vanilla_version_info(&vanilla_manifest, &version.minecraft_version)?;
+                let server_dir = output_dir.join(&build_name);
+                let mapping = build_server(
+                    version,
+                    &build_name,
+                    &vanilla_version_info,
+                    &server_dir,
+                    work_dir,
+                )
+                .await?;
+
+                // synced separately, so files of other servers in output_dir are left alone
+                events::stage("syncing_output");
+                let stats = sync_mapping_cached(&server_dir, &mapping, &mut hash_cache).await?;
+                info!(
+                    "Synced {} files of server {} (copied {}, deleted {})",
+                    stats.total_files, &build_name, stats.copied_files, stats.deleted_files
+                );
+                events::emit(Event::Synced {
+                    instance: Some(&build_name),
+                    stats,
+                });
+                anyhow::Ok(())
+            };
+            events::instance_scope(build_name.clone(), job).await?;
+        }
+
+        hash_cache.prune();
+        hash_cache.save(&hash_cache_path).await?;
+        Ok(())
+    }
+
+    /// Upload `output_dir` to the S3-compatible storage from the `publish` options.
+    pub async fn publish(
+        &self,
+        output_dir: &Path,
+        work_dir: &Path,
+        delete_orphans: bool,
+    ) -> anyhow::Result<()> {
+        let hash_cache_path = get_hash_cache_path(work_dir);
+        let mut hash_cache = HashCache::load(&hash_cache_path).await;
+        publish(&self.publish, output_dir, &mut hash_cache, delete_orphans).await?;
+        hash_cache.save(&hash_cache_path).await?;
+        Ok(())
+    }
+
+    async fn build(
+        mut self,
+        output_dir: &Path,
+        work_dir: &Path,
+        delete_remote_instances: Option<&HashSet<String>>,
+        dry_run: bool,
+        jobs: usize,
+    ) -> anyhow::Result<BuildResult> {
+        if let Some(command) = &self.exec_before_all {
+            exec_string_command(command).await?;
+        }
+
+        info!("Fetching version manifest");
+        let vanilla_manifest = VersionManifest::fetch(VANILLA_MANIFEST_URL).await?;
+
         if delete_remote_instances.is_some() && self.version_manifest_url.is_none() {
             warn!(
                 "--delete-remote flag is set but version_manifest_url spec option is not; ignoring the flag"
             );
         }
 
+        let mut deleted_remote_instances = vec![];
         let mut version_manifest = if let Some(version_manifest_url) = &self.version_manifest_url {
             info!("Fetching remote version manifest from: {version_manifest_url}");
             match VersionManifest::fetch(version_manifest_url).await {
@@ -119,12 +407,16 @@ impl Spec {
                     if let Some(to_delete) = delete_remote_instances
                         && !to_delete.is_empty()
                     {
-                        let before = manifest.versions.len();
                         manifest.versions.retain(|v| {
                             let name = v.get_name();
-                            !to_delete.contains(name.as_str())
+                            if to_delete.contains(name.as_str()) {
+                                deleted_remote_instances.push(name);
+                                false
+                            } else {
+                                true
+                            }
                         });
-                        let removed = before - manifest.versions.len();
+                        let removed = deleted_remote_instances.len();
                         if removed > 0 {
                             info!("Removed {removed} remote instance(s) from fetched manifest");
                         } else {
@@ -143,180 +435,335 @@ impl Spec {
         } else {
             VersionManifest { versions: vec![] }
         };
-        let mut synced_metadata = HashSet::new();
-        let mut mapping = HashMap::new();
-
-        for version in self.instances {
-            if let Some(command) = &version.exec_before {
-                exec_string_command(command).await?;
-            }
 
-            let vanilla_version_info =
-                get_vanilla_version_info(&vanilla_manifest, &version.minecraft_version)?;
+        let hash_cache_path = get_hash_cache_path(work_dir);
+        let context = BuildContext {
+            vanilla_manifest,
+            output_dir,
+            work_dir,
+            dry_run,
+            synced_metadata: Mutex::new(HashMap::new()),
+            hash_cache: Mutex::new(HashCache::load(&hash_cache_path).await),
+            minecraft_version_locks: KeyedLocks::default(),
+            sync_locks: KeyedLocks::default(),
+            download_locks: KeyedLocks::default(),
+        };
 
-            let progress_bar = Arc::new(TerminalProgressBar::new());
+        // results come in the order of the spec, so the manifest and the mapping don't depend on timing
+        let instances = std::mem::take(&mut self.instances);
+        let results: Vec<Option<InstanceResult>> = stream::iter(instances)
+            .map(|version| {
+                events::instance_scope(
+                    version.get_build_name(),
+                    self.build_instance(version, &context),
+                )
+            })
+            .buffered(jobs.max(1))
+            .try_collect()
+            .await?;
 
-            let generator: Box<dyn VersionGenerator> = match version.loader_name.as_str() {
-                "vanilla" => {
-                    if version.loader_version.is_some() {
-                        warn!("Ignoring loader version for vanilla version");
-                    }
+        let mut mapping = HashMap::new();
+        let mut history_sources = HashSet::new();
+        for result in results.into_iter().flatten() {
+            version_manifest
+                .versions
+                .retain(|v| !v.is_same_instance(&result.version_info));
+            version_manifest.versions.push(result.version_info);
+
+            mapping.extend(result.mapping);
+            // files of the current builds take precedence over the ones kept for history
+            for (target, source) in result.history_mapping {
+                history_sources.insert(source.clone());
+                mapping.entry(target).or_insert(source);
+            }
+        }
 
-                    Box::new(VanillaGenerator::new(
-                        version.name.clone(),
-                        vanilla_version_info,
-                    ))
-                }
+        Ok(BuildResult {
+            mapping,
+            version_manifest,
+            deleted_remote_instances,
+            hash_cache: context.hash_cache.into_inner(),
+            history_sources,
+        })
+    }
 
-                "fabric" => Box::new(FabricGenerator::new(
-                    version.name.clone(),
-                    vanilla_version_info,
-                    version.loader_version.clone(),
-                )),
+    async fn build_instance(
+        &self,
+        mut version: Instances,
+        context: &BuildContext<'_>,
+    ) -> anyhow::Result<Option<InstanceResult>> {
+        let BuildContext {
+            output_dir,
+            work_dir,
+            ..
+        } = *context;
+
+        let build_name = version.get_build_name();
+        if let Some(command) = &version.exec_before {
+            exec_string_command(command).await?;
+        }
 
-                "forge" => Box::new(ForgeGenerator::new(
-                    version.name.clone(),
-                    vanilla_version_info,
-                    Loader::Forge,
-                    version.loader_version.clone(),
-                    progress_bar.clone(),
-                )),
+        let vanilla_version_info =
+            get_vanilla_version_info(&context.vanilla_manifest, &version.minecraft_version)?;
+
+        // instances of one minecraft version share the vanilla metadata, it is downloaded
+        // by the first one, the generators below only read it
+        {
+            let _guard = context
+                .minecraft_version_locks
+                .lock(&version.minecraft_version)
+                .await;
+            VersionMetadata::read_or_download(
+                &vanilla_version_info.get_parent_metadata_info(),
+                &get_versions_dir(work_dir),
+            )
+            .await?;
+        }
 
-                "neoforge" => Box::new(ForgeGenerator::new(
-                    version.name.clone(),
-                    vanilla_version_info,
-                    Loader::Neoforge,
-                    version.loader_version.clone(),
-                    progress_bar.clone(),
-                )),
+        let progress_bar = Arc::new(BuilderProgressBar::new());
 
-                _ => {
-                    error!("Unsupported loader name: {}", version.loader_name);
-                    continue;
+        let generator: Box<dyn VersionGenerator> = match version.loader_name.as_str() {
+            "vanilla" => {
+                if version.loader_version.is_some() {
+                    warn!("Ignoring loader version for vanilla version");
                 }
-            };
-
-            let mut workdir_paths_to_copy = vec![];
 
-            let mut result = generator.generate(work_dir).await?;
-            let mut replaced_metadata = HashMap::new();
-            if self.replace_download_urls {
-                let versions_dir = get_versions_dir(output_dir);
-                let replaced_metadata_dir = get_replaced_metadata_dir(work_dir);
-
-                for metadata in result.metadata.iter_mut() {
-                    if synced_metadata.contains(&metadata.id) {
-                        info!("Skipping {}, it is already synced", &metadata.id);
-                        continue;
-                    }
-                    info!("Syncing {}", &metadata.id);
-
-                    let sync_result = sync_version(metadata, work_dir).await?;
-                    if let Some(asset_index) = &metadata.asset_index {
-                        let assets_dir = get_assets_dir(work_dir);
-                        let asset_index_path =
-                            AssetsMetadata::get_path(&assets_dir, &asset_index.id).await?;
-                        workdir_paths_to_copy.push(asset_index_path);
-                    }
-                    workdir_paths_to_copy.extend(sync_result.paths_to_copy);
+                Box::new(VanillaGenerator::new(
+                    build_name.clone(),
+                    vanilla_version_info,
+                ))
+            }
 
-                    replace_download_urls(metadata, &self.download_server_base, work_dir).await?;
-                    metadata.save(&replaced_metadata_dir).await?;
+            "fabric" => Box::new(FabricGenerator::new(
+                build_name.clone(),
+                vanilla_version_info,
+                version.loader_version.clone(),
+            )),
+
+            "quilt" => Box::new(QuiltGenerator::new(
+                build_name.clone(),
+                vanilla_version_info,
+                version.loader_version.clone(),
+            )),
+
+            "forge" => Box::new(ForgeGenerator::new(
+                build_name.clone(),
+                vanilla_version_info,
+                Loader::Forge,
+                version.loader_version.clone(),
+                progress_bar.clone(),
+            )),
+
+            "neoforge" => Box::new(ForgeGenerator::new(
+                build_name.clone(),
+                vanilla_version_info,
+                Loader::Neoforge,
+                version.loader_version.clone(),
+                progress_bar.clone(),
+            )),
+
+            _ => {
+                error!("Unsupported loader name: {}", version.loader_name);
+                return Ok(None);
+            }
+        };
 
-                    synced_metadata.insert(metadata.id.clone());
+        // the job hashes into its own copy of the cache, so jobs don't wait for each other
+        let mut hash_cache = context.hash_cache.lock().await.fork();
+        let mut workdir_paths_to_copy = vec![];
+        let mut mapping = HashMap::new();
 
-                    let replaced_metadata_path =
-                        get_metadata_path(&replaced_metadata_dir, &metadata.id);
-                    replaced_metadata.insert(metadata.id.clone(), replaced_metadata_path.clone());
-                    mapping.insert(
-                        get_metadata_path(&versions_dir, &metadata.id),
-                        replaced_metadata_path,
-                    );
+        events::stage("generating");
+        let mut result = generator.generate(work_dir).await?;
+        let mut replaced_metadata = HashMap::new();
+        if self.replace_download_urls {
+            let replaced_metadata_dir = get_replaced_metadata_dir(work_dir);
+
+            events::stage("syncing_version");
+            for metadata in result.metadata.iter_mut() {
+                // instances with the same metadata or asset index wait for the first one to sync it
+                let _guard = context.sync_locks.lock(&metadata.id).await;
+                let _assets_guard = match &metadata.asset_index {
+                    Some(asset_index) => Some(
+                        context
+                            .sync_locks
+                            .lock(&format!("assets/{}", asset_index.id))
+                            .await,
+                    ),
+                    None => None,
+                };
+                let synced_path = context
+                    .synced_metadata
+                    .lock()
+                    .await
+                    .get(&metadata.id)
+                    .cloned();
+                if let Some(replaced_metadata_path) = synced_path {
+                    info!("Skipping {}, it is already synced", &metadata.id);
+                    replaced_metadata.insert(metadata.id.clone(), replaced_metadata_path);
+                    continue;
                 }
-            } else {
-                let versions_dir = get_versions_dir(work_dir);
-                for metadata in result.metadata.iter_mut() {
-                    workdir_paths_to_copy.push(get_metadata_path(&versions_dir, &metadata.id));
+                info!("Syncing {}", &metadata.id);
+
+                let sync_result = sync_version(metadata, work_dir).await?;
+                if let Some(asset_index) = &metadata.asset_index {
+                    let assets_dir = get_assets_dir(work_dir);
+                    let asset_index_path =
+                        AssetsMetadata::get_path(&assets_dir, &asset_index.id).await?;
+                    workdir_paths_to_copy.push(asset_index_path);
                 }
+                workdir_paths_to_copy.extend(sync_result.paths_to_copy);
+
+                replace_download_urls(
+                    metadata,
+                    &self.download_server_base,
+                    work_dir,
+                    &mut hash_cache,
+                )
+                .await?;
+                metadata.save(&replaced_metadata_dir).await?;
+
+                let replaced_metadata_path =
+                    get_metadata_path(&replaced_metadata_dir, &metadata.id);
+                context
+                    .synced_metadata
+                    .lock()
+                    .await
+                    .insert(metadata.id.clone(), replaced_metadata_path.clone());
+                replaced_metadata.insert(metadata.id.clone(), replaced_metadata_path);
             }
-            workdir_paths_to_copy.extend(result.extra_libs_paths.clone());
+        }
+        workdir_paths_to_copy.extend(result.extra_libs_paths.clone());
 
-            let resources_url_base = if self.replace_download_urls {
-                self.resources_url_base.clone()
-            } else {
-                None
-            };
+        let resources_url_base = if self.replace_download_urls {
+            self.resources_url_base.clone()
+        } else {
+            None
+        };
 
-            let include_config = if let Some(include_from) = version.include_from {
-                Some(IncludeConfig {
-                    include: version.include,
-                    include_from,
-                    download_server_base: self.download_server_base.clone(),
-                    resources_url_base,
-                })
-            } else {
-                if !version.include.is_empty() {
-                    warn!("Ignoring include, include_from is not set");
-                }
-                None
-            };
+        events::stage("extra_metadata");
+        download_remote_files(&mut version.include, work_dir, &mut hash_cache).await?;
+        let has_remote_files = version
+            .include
+            .iter()
+            .any(|rule| !rule.remote_files.is_empty());
+        let include_config = if version.include_from.is_some() || has_remote_files {
+            Some(IncludeConfig {
+                include: version.include,
+                include_from: version.include_from,
+                download_server_base: self.download_server_base.clone(),
+                resources_url_base,
+            })
+        } else {
+            if !version.include.is_empty() {
+                warn!("Ignoring include, include_from is not set");
+            }
+            None
+        };
 
-            let extra_generator = ExtraMetadataGenerator::new(
-                version.name.clone(),
-                include_config,
-                result.extra_libs_paths,
-                version.auth_backend,
-                version.recommended_xmx,
-            );
-            let extra_generator_result = extra_generator.generate(work_dir).await?;
-            mapping.extend(extra_generator_result.include_mapping.into_iter().map(
-                |(include_entry, source_path)| {
-                    let minecraft_dir = get_minecraft_dir(output_dir, &version.name);
-                    (minecraft_dir.join(include_entry), source_path)
-                },
-            ));
-
-            let versions_extra_dir = get_versions_extra_dir(work_dir);
-            workdir_paths_to_copy.push(get_extra_metadata_path(&versions_extra_dir, &version.name));
-
-            info!("Getting version info for {}", &version.name);
-            let version_info = get_version_info(
+        // the launcher takes the java version from the vanilla metadata
+        let java_version = result
+            .metadata
+            .first()
+            .and_then(|metadata| metadata.java_version.as_ref())
+            .map_or("8".to_string(), |java_version| {
+                java_version.major_version.to_string()
+            });
+        let java_runtimes = download_java_runtimes(
+            &java_version,
+            &self.java_platforms,
+            work_dir,
+            &self.download_server_base,
+            &context.download_locks,
+            &mut hash_cache,
+        )
+        .await?;
+
+        let extra_generator = ExtraMetadataGenerator::new(
+            build_name.clone(),
+            include_config,
+            result.extra_libs_paths,
+            version.auth_backend,
+            version.recommended_xmx,
+            java_runtimes,
+        );
+        let extra_generator_result = extra_generator.generate(work_dir, &mut hash_cache).await?;
+        let mut objects = extra_generator_result.object_sources;
+
+        let versions_extra_dir = get_versions_extra_dir(work_dir);
+        let extra_metadata_path = get_extra_metadata_path(&versions_extra_dir, &build_name);
+
+        info!("Getting version info for {}", &build_name);
+        let mut version_info = get_version_info(
+            work_dir,
+            &result.metadata,
+            &build_name,
+            Some(self.download_server_base.as_str()),
+            &replaced_metadata,
+        )
+        .await?;
+        version_info.name = Some(version.name.clone());
+        version_info.channel = version.channel.clone();
+        objects.extend(map_version_objects(
+            &mut version_info,
+            |id| get_metadata_source_path(work_dir, id, &replaced_metadata),
+            &extra_metadata_path,
+            &self.download_server_base,
+        )?);
+        mapping.extend(objects.iter().map(|(sha1, source_path)| {
+            (
+                output_dir.join(get_rel_object_path(sha1)),
+                source_path.clone(),
+            )
+        }));
+
+        let workdir_mapping = get_mapping(output_dir, work_dir, &workdir_paths_to_copy)?;
+        let mut history_mapping = HashMap::new();
+        if self.history_size > 0 {
+            events::stage("history");
+            let snapshot = BuildSnapshot {
+                build_name: &build_name,
+                version_info: &version_info,
+                objects: &objects,
+                // java archives in work_dir are named after their release and never change
+                stable_objects: extra_generator_result
+                    .extra_metadata
+                    .java_runtimes
+                    .iter()
+                    .map(|runtime| runtime.sha1.clone())
+                    .collect(),
+                files: workdir_mapping.clone(),
+            };
+            let history_result = update_history(
+                snapshot,
+                self.history_size,
+                output_dir,
                 work_dir,
-                &result.metadata,
-                &version.name,
-                Some(self.download_server_base.as_str()),
-                &replaced_metadata,
+                context.dry_run,
             )
             .await?;
+            version_info.built_at = Some(history_result.built_at);
+            version_info.history = history_result.history;
+            history_mapping = history_result.mapping;
+        }
+        mapping.extend(workdir_mapping);
 
-            version_manifest
-                .versions
-                .retain(|v| v.get_name() != version_info.get_name());
-            version_manifest.versions.push(version_info);
-
-            mapping.extend(get_mapping(output_dir, work_dir, &workdir_paths_to_copy)?);
-
-            if let Some(command) = &version.exec_after {
+        if let Some(command) = &version.exec_after {
+            events::stage("exec_after");
+            if context.dry_run {
+                info!("Skipping exec_after of {} in plan mode", &build_name);
+            } else {
                 exec_string_command(command).await?;
             }
-
-            info!("Finished generating version {}", &version.name);
         }
 
-        info!("Syncing {} entries", mapping.len());
-        debug!("Sync mapping (target->source): {mapping:?}");
-        let stats = sync_mapping(output_dir, &mapping).await?;
-        info!(
-            "Synced {} files (copied {}, deleted {})",
-            stats.total_files, stats.copied_files, stats.deleted_files
-        );
+        info!("Finished generating version {}", &build_name);
+        context.hash_cache.lock().await.merge(hash_cache);
 
-        let manifest_path = get_manifest_path(output_dir);
-        version_manifest.save_to_file(&manifest_path).await?;
-
-        if let Some(command) = &self.exec_after_all {
-            exec_string_command(command).await?;
-        }
-        Ok(())
+        Ok(Some(InstanceResult {
+            version_info,
+            mapping,
+            history_mapping,
+        }))
     }
 }
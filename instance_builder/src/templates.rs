@@ -0,0 +1,226 @@
+use serde_json::{Map, Value};
+
+// these identify an instance and are never inherited
+const NOT_INHERITED: [&str; 3] = ["name", "channel", "extends"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum TemplateError {
+    #[error("Instances and templates must be JSON objects")]
+    NotAnObject,
+    #[error("\"extends\" must be a string")]
+    BadExtends,
+    #[error("Nothing named \"{0}\" to extend")]
+    UnknownParent(String),
+    #[error("Cyclic \"extends\" chain: {0}")]
+    Cycle(String),
+}
+
+struct Sources<'a> {
+    templates: &'a Map<String, Value>,
+    instances: &'a [Value],
+}
+
+impl Sources<'_> {
+    // templates first, then instances by name, with " (channel)" for channel variants
+    fn get(&self, name: &str) -> Option<&Value> {
+        if let Some(template) = self.templates.get(name) {
+            return Some(template);
+        }
+        self.instances.iter().find(|instance| {
+            let instance_name = instance.get("name").and_then(Value::as_str);
+            match instance.get("channel").and_then(Value::as_str) {
+                Some(channel) => instance_name
+                    .is_some_and(|instance_name| format!("{instance_name} ({channel})") == name),
+                None => instance_name == Some(name),
+            }
+        })
+    }
+}
+
+fn merge(mut parent: Map<String, Value>, child: &Map<String, Value>) -> Map<String, Value> {
+    for key in NOT_INHERITED {
+        parent.remove(key);
+    }
+    for (key, value) in child {
+        if key == "include"
+            && let (Some(Value::Array(parent_include)), Value::Array(child_include)) =
+                (parent.get_mut(key), value)
+        {
+            parent_include.extend(child_include.iter().cloned());
+        } else {
+            parent.insert(key.clone(), value.clone());
+        }
+    }
+    parent.remove("extends");
+    parent
+}
+
+fn resolve(
+    entry: &Value,
+    sources: &Sources,
+    chain: &mut Vec<String>,
+) -> anyhow::Result<Map<String, Value>> {
+    let entry = entry.as_object().ok_or(TemplateError::NotAnObject)?;
+    let Some(parent_name) = entry.get("extends") else {
+        return Ok(entry.clone());
+    };
+    let parent_name = parent_name.as_str().ok_or(TemplateError::BadExtends)?;
+
+    if chain.iter().any(|name| name == parent_name) {
+        chain.push(parent_name.to_string());
+        return Err(TemplateError::Cycle(chain.join(" -> ")).into());
+    }
+    let parent = sources
+        .get(parent_name)
+        .ok_or(TemplateError::UnknownParent(parent_name.to_string()))?;
+
+    chain.push(parent_name.to_string());
+    let parent = resolve(parent, sources, chain)?;
+    chain.pop();
+
+    Ok(merge(parent, entry))
+}
+
+/// Replace `extends` of every instance with the fields it inherits and drop the templates.
+pub fn resolve_templates(spec: &mut Value) -> anyhow::Result<()> {
+    let spec = spec.as_object_mut().ok_or(TemplateError::NotAnObject)?;
+    let templates = match spec.remove("templates") {
+        Some(Value::Object(templates)) => templates,
+        Some(_) => return Err(TemplateError::NotAnObject.into()),
+        None => Map::new(),
+    };
+    let Some(Value::Array(instances)) = spec.get("instances") else {
+        return Ok(());
+    };
+
+    let sources = Sources {
+        templates: &templates,
+        instances,
+    };
+    let resolved = instances
+        .iter()
+        .map(|instance| resolve(instance, &sources, &mut vec![]).map(Value::Object))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    spec.insert("instances".to_string(), Value::Array(resolved));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn resolve_instances(mut spec: Value) -> anyhow::Result<Value> {
+        resolve_templates(&mut spec)?;
+        assert!(spec.get("templates").is_none());
+        Ok(spec["instances"].clone())
+    }
+
+    #[test]
+    fn test_merge() {
+        let instances = resolve_instances(json!({
+            "templates": {
+                "base": {
+                    "name": "Base",
+                    "channel": "beta",
+                    "loader_name": "fabric",
+                    "recommended_xmx": "4G",
+                    "include": [{ "path": "mods" }]
+                }
+            },
+            "instances": [{
+                "name": "Survival",
+                "extends": "base",
+                "minecraft_version": "1.21.1",
+                "recommended_xmx": null,
+                "include": [{ "path": "config" }]
+            }]
+        }))
+        .unwrap();
+        assert_eq!(
+            instances,
+            json!([{
+                "loader_name": "fabric",
+                "recommended_xmx": null,
+                "include": [{ "path": "mods" }, { "path": "config" }],
+                "name": "Survival",
+                "minecraft_version": "1.21.1"
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parent_lookup() {
+        let instances = resolve_instances(json!({
+            "templates": {
+                "Survival": { "loader_name": "forge" }
+            },
+            "instances": [
+                { "name": "Survival", "minecraft_version": "1.20.1", "loader_name": "vanilla" },
+                { "name": "Survival", "channel": "beta", "minecraft_version": "1.21.1" },
+                { "name": "From template", "extends": "Survival" },
+                { "name": "From channel", "extends": "Survival (beta)" },
+                { "name": "Chained", "extends": "From channel", "loader_name": "quilt" }
+            ]
+        }))
+        .unwrap();
+        // templates take precedence over instances with the same name
+        assert_eq!(
+            instances[2],
+            json!({ "loader_name": "forge", "name": "From template" })
+        );
+        assert_eq!(
+            instances[3],
+            json!({ "minecraft_version": "1.21.1", "name": "From channel" })
+        );
+        assert_eq!(
+            instances[4],
+            json!({ "minecraft_version": "1.21.1", "name": "Chained", "loader_name": "quilt" })
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = resolve_instances(json!({
+            "templates": {
+                "a": { "extends": "b" },
+                "b": { "extends": "a" }
+            },
+            "instances": [{ "name": "Survival", "extends": "a" }]
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TemplateError>(),
+            Some(TemplateError::Cycle(chain)) if chain == "a -> b -> a"
+        ));
+
+        let error = resolve_instances(json!({
+            "instances": [{ "name": "Survival", "extends": "Survival" }]
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TemplateError>(),
+            Some(TemplateError::Cycle(_))
+        ));
+
+        let error = resolve_instances(json!({
+            "instances": [{ "name": "Survival", "extends": "missing" }]
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TemplateError>(),
+            Some(TemplateError::UnknownParent(name)) if name == "missing"
+        ));
+
+        let error = resolve_instances(json!({
+            "instances": [{ "name": "Survival", "extends": 1 }]
+        }))
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TemplateError>(),
+            Some(TemplateError::BadExtends)
+        ));
+    }
+}
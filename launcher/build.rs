@@ -52,6 +52,19 @@ fn main() {
             }
         }
     }
+    // comma-separated hex-encoded Ed25519 public keys the version manifest must be signed with
+    let trusted_manifest_keys = get_env("TRUSTED_MANIFEST_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .map(|key| format!("\"{key}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    config_content.push_str(&format!(
+        "pub const TRUSTED_MANIFEST_KEYS: &[&str] = &[{trusted_manifest_keys}];\n"
+    ));
+
     let use_native_glfw_default = get_env("USE_NATIVE_GLFW_DEFAULT")
         .unwrap_or_else(|| "false".to_string())
         .parse::<bool>()
@@ -2,6 +2,7 @@ use egui::RichText;
 use log::error;
 use shared::progress::ProgressBar;
 use shared::utils::is_connect_error;
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
@@ -29,6 +30,7 @@ fn sync_instance(
     force_overwrite: bool,
     launcher_dir: &Path,
     assets_dir: &Path,
+    optional_groups: HashMap<String, bool>,
     progress_bar: Arc<dyn ProgressBar<LangMessage>>,
 ) -> BackgroundTask<anyhow::Result<()>> {
     let launcher_dir = launcher_dir.to_path_buf();
@@ -43,6 +45,7 @@ fn sync_instance(
             force_overwrite,
             &launcher_dir,
             &assets_dir,
+            &optional_groups,
             progress_bar_clone,
         )
         .await
@@ -133,12 +136,18 @@ impl InstanceSyncState {
         if let Some(mut task) = self.instance_sync_task.take() {
             task.cancel();
         }
+        let optional_groups = config
+            .optional_groups
+            .get(selected_version_metadata.get_name())
+            .cloned()
+            .unwrap_or_default();
         self.instance_sync_task = Some(sync_instance(
             runtime,
             selected_version_metadata,
             force_overwrite,
             &config.get_launcher_dir(),
             &config.get_assets_dir(),
+            optional_groups,
             self.instance_sync_progress_bar.clone(),
         ));
     }
@@ -10,8 +10,9 @@ use crate::config::runtime_config::Config;
 use crate::lang::{Lang, LangMessage};
 use crate::version::complete_version_metadata::CompleteVersionMetadata;
 
-use shared::java;
+use shared::java::{self, JavaArchive};
 use shared::progress::{ProgressBar, Unit};
+use shared::version::extra_version_metadata::JavaRuntime;
 
 use super::background_task::{BackgroundTask, BackgroundTaskResult};
 use super::colors;
@@ -75,14 +76,17 @@ fn download_java(
     runtime: &Runtime,
     required_version: &str,
     java_dir: &Path,
+    hosted: Option<&JavaRuntime>,
     progress_bar: Arc<dyn ProgressBar<LangMessage>>,
 ) -> BackgroundTask<JavaDownloadResult> {
     let progress_bar_clone = progress_bar.clone();
     let required_version = required_version.to_string();
     let java_dir = java_dir.to_path_buf();
+    let hosted = hosted.map(JavaArchive::from);
     let fut = async move {
         progress_bar_clone.set_message(LangMessage::DownloadingJava);
-        let result = java::download_java(&required_version, &java_dir, progress_bar_clone).await;
+        let result =
+            java::download_java(&required_version, &java_dir, hosted, progress_bar_clone).await;
         match result {
             Ok(java_installation) => JavaDownloadResult {
                 status: JavaDownloadStatus::Downloaded,
@@ -148,6 +152,7 @@ impl JavaState {
             runtime,
             &metadata.get_java_version(),
             &java_dir,
+            metadata.get_java_runtime(),
             self.java_download_progress_bar.clone(),
         ));
     }
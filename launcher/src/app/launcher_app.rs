@@ -174,12 +174,21 @@ impl LauncherApp {
                 let mut all_names: HashSet<String> =
                     local_instance_names.clone().into_iter().collect();
                 all_names.extend(remote_instance_names.clone());
+                // only instances created in the launcher can be exported
+                let selected_local_instance = self
+                    .config
+                    .selected_instance_name
+                    .as_ref()
+                    .and_then(|name| self.instance_storage.get_instance(name))
+                    .filter(|instance| instance.manifest_url.is_none())
+                    .map(|instance| instance.version_info);
                 let new_instance_result = self.new_instance_state.render_ui(
                     &self.runtime,
                     ui,
                     &mut self.config,
                     &all_names,
                     &local_instance_names,
+                    selected_local_instance.as_ref(),
                 );
 
                 if let Some(instance_to_delete) = new_instance_result.instance_to_delete {
@@ -193,12 +202,22 @@ impl LauncherApp {
                 }
 
                 let selected_instance = self.metadata_state.get_version_metadata(&self.config);
-                self.settings_state.render_instance_settings(
+                let instance_settings_result = self.settings_state.render_instance_settings(
                     ui,
                     &self.runtime,
                     &mut self.config,
                     selected_instance.as_deref(),
+                    &mut self.instance_storage,
                 );
+                if instance_settings_result.files_changed {
+                    self.instance_sync_state.reset_status();
+                }
+                if instance_settings_result.build_changed {
+                    // the metadata of the previous build is cached under the same name
+                    self.instance_sync_state.cancel_sync();
+                    self.metadata_state.clear();
+                    self.set_metadata_task(ctx);
+                }
 
                 let selected_version_changed = self.manifest_state.render_combo_box(
                     ui,
@@ -1,7 +1,10 @@
-use crate::{config::runtime_config::Config, lang::LangMessage};
+use crate::{
+    config::{build_config, runtime_config::Config},
+    lang::LangMessage,
+};
 
 use egui::RichText;
-use log::error;
+use log::{error, warn};
 use shared::utils::is_connect_error;
 use shared::version::version_manifest::VersionManifest;
 use tokio::runtime::Runtime;
@@ -24,6 +27,24 @@ struct ManifestFetchResult {
     manifest: Option<VersionManifest>,
 }
 
+async fn fetch_verified_manifest(url: &str) -> anyhow::Result<VersionManifest> {
+    let trusted_keys = build_config::get_trusted_manifest_keys();
+    if trusted_keys.is_empty() {
+        return VersionManifest::fetch(url).await;
+    }
+
+    match VersionManifest::fetch_signed(url, trusted_keys).await {
+        Ok(manifest) => Ok(manifest),
+        Err(e) if is_connect_error(&e) => Err(e),
+        // manifests added by the user are allowed to be unsigned, the default one is not
+        Err(e) if url != build_config::get_default_version_manifest_url() => {
+            warn!("Version manifest {url} failed signature verification, using it anyway: {e}");
+            VersionManifest::fetch(url).await
+        }
+        Err(e) => Err(e.context("Version manifest signature verification failed")),
+    }
+}
+
 fn fetch_manifest<Callback>(
     runtime: &tokio::runtime::Runtime,
     url: String,
@@ -33,7 +54,7 @@ where
     Callback: FnOnce() + Send + 'static,
 {
     let fut = async move {
-        let result = VersionManifest::fetch(&url).await;
+        let result = fetch_verified_manifest(&url).await;
         match result {
             Ok(manifest) => ManifestFetchResult {
                 status: FetchStatus::Fetched,
@@ -1,21 +1,26 @@
 use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use log::error;
+use log::{error, warn};
 use maplit::hashmap;
 use shared::generate::extra::ExtraMetadataGenerator;
 use shared::generate::manifest::get_version_info;
+use shared::hash_cache::HashCache;
 use shared::loader_generator::fabric::{FabricGenerator, FabricVersionsMeta};
 use shared::loader_generator::forge::{
     ForgeGenerator, ForgeMavenMetadata, ForgePromotions, Loader, NeoforgeMavenMetadata,
 };
 use shared::loader_generator::generator::VersionGenerator;
+use shared::loader_generator::quilt::{QuiltGenerator, QuiltVersionsMeta};
 use shared::loader_generator::vanilla::VanillaGenerator;
-use shared::paths::get_minecraft_dir;
+use shared::mrpack::MrpackExporter;
+use shared::path_filter::PathFilter;
+use shared::paths::{get_minecraft_dir, get_versions_dir};
 use shared::progress::NoProgressBar;
 use shared::utils::{VANILLA_MANIFEST_URL, get_vanilla_version_info, is_connect_error};
 use shared::version::version_manifest::{VersionInfo, VersionManifest};
+use shared::version::version_metadata::VersionMetadata;
 use tokio::runtime::Runtime;
 
 use crate::{
@@ -64,6 +69,8 @@ fn fetch_all_metadata(
 
 struct PerVersionMetadata {
     fabric_metadata: FabricVersionsMeta,
+    // quilt is left out of the loader list if its meta server is unavailable
+    quilt_metadata: Option<QuiltVersionsMeta>,
 }
 
 fn fetch_per_version_metadata(
@@ -73,9 +80,21 @@ fn fetch_per_version_metadata(
 ) -> BackgroundTask<anyhow::Result<PerVersionMetadata>> {
     let version_id = version_id.to_string();
     let fut = async move {
-        return anyhow::Result::Ok(PerVersionMetadata {
-            fabric_metadata: FabricVersionsMeta::fetch(&version_id).await?,
-        });
+        let (fabric_metadata, quilt_metadata) = futures::join!(
+            FabricVersionsMeta::fetch(&version_id),
+            QuiltVersionsMeta::fetch(&version_id),
+        );
+        let quilt_metadata = match quilt_metadata {
+            Ok(quilt_metadata) => Some(quilt_metadata),
+            Err(e) => {
+                warn!("Error fetching Quilt versions:\n{e:?}");
+                None
+            }
+        };
+        anyhow::Result::Ok(PerVersionMetadata {
+            fabric_metadata: fabric_metadata?,
+            quilt_metadata,
+        })
     };
 
     let ctx = ctx.clone();
@@ -154,6 +173,7 @@ pub struct RenderUIResult {
 
 const VANILLA_LOADER: &str = "Vanilla";
 const FABRIC_LOADER: &str = "Fabric";
+const QUILT_LOADER: &str = "Quilt";
 const FORGE_LOADER: &str = "Forge";
 const NEOFORGE_LOADER: &str = "NeoForge";
 
@@ -201,6 +221,12 @@ fn create_new_instance(
                 Some(loader_version),
             )),
 
+            QUILT_LOADER => Box::new(QuiltGenerator::new(
+                instance_name.to_string(),
+                vanilla_info,
+                Some(loader_version),
+            )),
+
             FORGE_LOADER => Box::new(ForgeGenerator::new(
                 instance_name.to_string(),
                 vanilla_info,
@@ -230,8 +256,11 @@ fn create_new_instance(
             generator_result.extra_libs_paths,
             None,
             None,
+            vec![],
         );
-        let _ = extra_generator.generate(&launcher_dir).await?;
+        let _ = extra_generator
+            .generate(&launcher_dir, &mut HashCache::default())
+            .await?;
 
         let version_info = get_version_info(
             &launcher_dir,
@@ -255,6 +284,58 @@ fn create_new_instance(
     )
 }
 
+// files the game creates while playing, they don't belong in a modpack
+const EXPORT_EXCLUDE: &[&str] = &[
+    "logs/**",
+    "crash-reports/**",
+    "screenshots/**",
+    "saves/**",
+    "usercache.json",
+    "usernamecache.json",
+];
+
+fn export_instance(
+    runtime: &Runtime,
+    ctx: &egui::Context,
+    launcher_dir: &Path,
+    version_info: VersionInfo,
+    archive_path: PathBuf,
+) -> BackgroundTask<anyhow::Result<()>> {
+    let launcher_dir = launcher_dir.to_path_buf();
+    let fut = async move {
+        let versions_dir = get_versions_dir(&launcher_dir);
+        let mut metadata = vec![];
+        for metadata_info in version_info.inherits_from.iter() {
+            metadata.push(VersionMetadata::read_local(&versions_dir, &metadata_info.id).await?);
+        }
+        metadata.push(VersionMetadata::read_local(&versions_dir, &version_info.id).await?);
+
+        // local instances have no included files, everything players added is packed
+        let mut exporter = MrpackExporter::new(&version_info, &metadata)?;
+        let exclude: Vec<String> = EXPORT_EXCLUDE.iter().map(|x| x.to_string()).collect();
+        exporter.add_overrides(
+            &get_minecraft_dir(&launcher_dir, &version_info.get_name()),
+            &PathFilter::new(&[], &exclude)?,
+        )?;
+        exporter.write(&archive_path)
+    };
+
+    let ctx = ctx.clone();
+    BackgroundTask::with_callback(
+        fut,
+        runtime,
+        Box::new(move || {
+            ctx.request_repaint();
+        }),
+    )
+}
+
+enum InstanceExportState {
+    NotExported,
+    Exported(String),
+    Error(String),
+}
+
 enum NewInstanceGenerateState {
     NoError,
     Offline,
@@ -275,6 +356,9 @@ pub struct NewInstanceState {
 
     instance_generate_task: Option<BackgroundTask<anyhow::Result<VersionInfo>>>,
     instance_generate_state: NewInstanceGenerateState,
+    // name of the exported instance and the task
+    instance_export_task: Option<(String, BackgroundTask<anyhow::Result<()>>)>,
+    instance_export_state: InstanceExportState,
     delete_window_open: bool,
     selected_instance_to_delete: String,
     confirm_delete: bool,
@@ -296,6 +380,8 @@ impl NewInstanceState {
 
             instance_generate_task: None,
             instance_generate_state: NewInstanceGenerateState::NoError,
+            instance_export_task: None,
+            instance_export_state: InstanceExportState::NotExported,
             delete_window_open: false,
             selected_instance_to_delete: String::new(),
             confirm_delete: false,
@@ -338,6 +424,7 @@ impl NewInstanceState {
         config: &mut Config,
         existing_names: &HashSet<String>,
         local_instance_names: &Vec<String>,
+        selected_local_instance: Option<&VersionInfo>,
     ) -> RenderUIResult {
         let lang = config.lang;
 
@@ -354,6 +441,21 @@ impl NewInstanceState {
             self.curent_metadata_state.take_from_task(task.unwrap());
             self.instance_loader = VANILLA_LOADER.to_string();
         }
+        if let Some((_, task)) = self.instance_export_task.as_ref()
+            && task.has_result()
+        {
+            let (instance_name, task) = self.instance_export_task.take().unwrap();
+            self.instance_export_state = match task.take_result() {
+                BackgroundTaskResult::Finished(Ok(())) => {
+                    InstanceExportState::Exported(instance_name)
+                }
+                BackgroundTaskResult::Finished(Err(e)) => {
+                    error!("Error exporting instance:\n{e:?}");
+                    InstanceExportState::Error(instance_name)
+                }
+                BackgroundTaskResult::Cancelled => InstanceExportState::NotExported,
+            };
+        }
 
         if let Some(selected_instance_name) = &config.selected_instance_name
             && ui.button("📂").clicked()
@@ -362,6 +464,42 @@ impl NewInstanceState {
             let _ = open::that(get_minecraft_dir(&launcher_dir, selected_instance_name));
         }
 
+        if let Some(version_info) = selected_local_instance {
+            let instance_name = version_info.get_name();
+            if self.instance_export_task.is_some() {
+                ui.label(LangMessage::ExportingInstance.to_string(lang));
+            } else {
+                if ui
+                    .button("📤")
+                    .on_hover_text(LangMessage::ExportInstance.to_string(lang))
+                    .clicked()
+                    && let Some(archive_path) = rfd::FileDialog::new()
+                        .set_file_name(format!("{instance_name}.mrpack"))
+                        .add_filter("Modrinth modpack", &["mrpack"])
+                        .save_file()
+                {
+                    let task = export_instance(
+                        runtime,
+                        ui.ctx(),
+                        &config.get_launcher_dir(),
+                        version_info.clone(),
+                        archive_path,
+                    );
+                    self.instance_export_task = Some((instance_name.clone(), task));
+                    self.instance_export_state = InstanceExportState::NotExported;
+                }
+                match &self.instance_export_state {
+                    InstanceExportState::Exported(name) if *name == instance_name => {
+                        ui.label(LangMessage::InstanceExported.to_string(lang));
+                    }
+                    InstanceExportState::Error(name) if *name == instance_name => {
+                        ui.label(LangMessage::InstanceExportError.to_string(lang));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
         if ui.button("-").clicked() {
             self.delete_window_open = true;
         }
@@ -432,13 +570,14 @@ impl NewInstanceState {
 
                 let versions = hashmap! {
                     FABRIC_LOADER.to_string() => current_metadata.fabric_metadata.get_versions().into_iter().map(|v| v.to_string()).collect(),
+                    QUILT_LOADER.to_string() => current_metadata.quilt_metadata.as_ref().map(|meta| meta.get_versions().into_iter().map(|v| v.to_string()).collect()).unwrap_or_default(),
                     FORGE_LOADER.to_string() => all_metadata.forge_metadata.get_matching_versions(&self.instance_version),
                     NEOFORGE_LOADER.to_string() => all_metadata.neoforge_metadata.get_matching_versions(&self.instance_version),
                 };
 
                 ui.horizontal(|ui| {
                     let mut loaders = vec![VANILLA_LOADER.to_string()];
-                    for loader in [FABRIC_LOADER, FORGE_LOADER, NEOFORGE_LOADER] {
+                    for loader in [FABRIC_LOADER, QUILT_LOADER, FORGE_LOADER, NEOFORGE_LOADER] {
                         let versions = versions.get(loader);
                         if let Some(versions) = versions
                             && !versions.is_empty() {
@@ -2,9 +2,13 @@ use egui::Widget as _;
 
 use crate::lang::{Lang, LangMessage};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use shared::progress::{ProgressBar, Unit};
 
+const SPEED_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const SPEED_SMOOTHING: f64 = 0.3;
+
 pub struct GuiProgressBar {
     state: Arc<Mutex<ProgressBarState>>,
     ctx: egui::Context,
@@ -18,6 +22,43 @@ pub struct ProgressBarState {
     pub message: Option<LangMessage>,
     pub finished: bool,
     pub unit: Option<Unit>,
+    // progress per second, smoothed over the last samples
+    pub speed: Option<f64>,
+    speed_sample: (Instant, u64),
+}
+
+impl ProgressBarState {
+    fn update_speed(&mut self) {
+        let now = Instant::now();
+        let (sample_time, sample_progress) = self.speed_sample;
+        let elapsed = now.duration_since(sample_time);
+        if elapsed < SPEED_SAMPLE_INTERVAL {
+            return;
+        }
+
+        let current_speed =
+            self.progress.saturating_sub(sample_progress) as f64 / elapsed.as_secs_f64();
+        self.speed = Some(match self.speed {
+            Some(speed) => speed * (1.0 - SPEED_SMOOTHING) + current_speed * SPEED_SMOOTHING,
+            None => current_speed,
+        });
+        self.speed_sample = (now, self.progress);
+    }
+
+    fn get_time_left(&self) -> Option<Duration> {
+        let speed = self.speed.filter(|speed| *speed > 0.0)?;
+        let left = self.total.saturating_sub(self.progress) as f64;
+        Some(Duration::from_secs_f64(left / speed))
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
 }
 
 impl GuiProgressBar {
@@ -29,6 +70,8 @@ impl GuiProgressBar {
                 message: None,
                 finished: false,
                 unit: None,
+                speed: None,
+                speed_sample: (Instant::now(), 0),
             })),
             ctx: ctx.clone(),
             last_update: Arc::new(Mutex::new(std::time::Instant::now())),
@@ -60,12 +103,15 @@ impl ProgressBar<LangMessage> for GuiProgressBar {
         state.total = length;
         state.progress = 0;
         state.finished = false;
+        state.speed = None;
+        state.speed_sample = (Instant::now(), 0);
         self.update_gui_if_needed();
     }
 
     fn inc(&self, amount: u64) {
         let mut state = self.state.lock().unwrap();
         state.progress += amount;
+        state.update_speed();
         self.update_gui_if_needed();
     }
 
@@ -79,6 +125,11 @@ impl ProgressBar<LangMessage> for GuiProgressBar {
         let mut state = self.state.lock().unwrap();
         state.unit = Some(unit);
     }
+
+    fn clear_unit(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.unit = None;
+    }
 }
 
 impl GuiProgressBar {
@@ -93,12 +144,22 @@ impl GuiProgressBar {
             .as_ref()
             .map(|u| u.size as f32)
             .unwrap_or(1.0);
-        let unit_name = progress_bar_state.unit.map(|u| u.name);
+        let unit_name = progress_bar_state.unit.as_ref().map(|u| u.name.clone());
 
         let progress_string = if let Some(unit_name) = unit_name {
             let progress = progress_bar_state.progress as f32 / unit_size;
             let total = progress_bar_state.total as f32 / unit_size;
-            format!("{progress:.2} / {total:.2} {unit_name}")
+            let mut progress_string = format!("{progress:.2} / {total:.2} {unit_name}");
+            if !progress_bar_state.finished
+                && let Some(speed) = progress_bar_state.speed
+                && let Some(time_left) = progress_bar_state.get_time_left()
+            {
+                let speed = speed as f32 / unit_size;
+                let time_left = LangMessage::TimeLeft(format_duration(time_left));
+                progress_string +=
+                    &format!(", {speed:.2} {unit_name}/s, {}", time_left.to_string(lang));
+            }
+            progress_string
         } else {
             format!(
                 "{} / {}",
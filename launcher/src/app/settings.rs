@@ -26,6 +26,14 @@ pub struct SettingsState {
     new_manifest_url: String,
 }
 
+#[derive(Default)]
+pub struct InstanceSettingsResult {
+    // a different channel or build was selected
+    pub build_changed: bool,
+    // the instance files have to be synced again
+    pub files_changed: bool,
+}
+
 fn map_xmx_slider_value(value: f64) -> String {
     let mb = utils::map_range(value, 0.0, 1.0, XMX_MIN as f64, get_xmx_max()) as u64;
     format!("{}M", ((mb + XMX_STEP / 2) / XMX_STEP) * XMX_STEP)
@@ -244,7 +252,8 @@ impl SettingsState {
         runtime: &Runtime,
         config: &mut Config,
         selected_metadata: Option<&CompleteVersionMetadata>,
-    ) {
+        instance_storage: &mut InstanceStorage,
+    ) -> InstanceSettingsResult {
         if ui
             .add_enabled(
                 selected_metadata.is_some() && !self.instance_settings_opened,
@@ -268,9 +277,153 @@ impl SettingsState {
         }
 
         if let Some(selected_metadata) = selected_metadata {
-            self.render_instance_settings_window(ui, runtime, config, selected_metadata);
+            self.render_instance_settings_window(
+                ui,
+                runtime,
+                config,
+                selected_metadata,
+                instance_storage,
+            )
         } else {
             self.instance_settings_opened = false;
+            InstanceSettingsResult::default()
+        }
+    }
+
+    fn render_optional_groups(
+        &mut self,
+        ui: &mut egui::Ui,
+        runtime: &Runtime,
+        config: &mut Config,
+        selected_metadata: &CompleteVersionMetadata,
+        instance_storage: &mut InstanceStorage,
+    ) -> bool {
+        let Some(extra) = selected_metadata.get_extra() else {
+            return false;
+        };
+        let groups = extra.get_optional_groups();
+        if groups.is_empty() {
+            return false;
+        }
+
+        let version_name = selected_metadata.get_name();
+        let mut changed = false;
+        ui.label(LangMessage::OptionalGroups.to_string(config.lang));
+        for group in groups {
+            let old_enabled = config
+                .optional_groups
+                .get(version_name)
+                .and_then(|groups| groups.get(&group.id))
+                .copied()
+                .unwrap_or(group.enabled_by_default);
+            let mut enabled = old_enabled;
+            let checkbox = ui.checkbox(&mut enabled, &group.name);
+            if let Some(description) = &group.description {
+                checkbox.on_hover_text(description);
+            }
+            if enabled != old_enabled {
+                config
+                    .optional_groups
+                    .entry(version_name.to_string())
+                    .or_default()
+                    .insert(group.id.clone(), enabled);
+                changed = true;
+            }
+        }
+
+        if changed {
+            config.save();
+            runtime.block_on(instance_storage.mark_pending_sync(config, version_name));
+        }
+        changed
+    }
+
+    fn render_channel_selector(
+        &mut self,
+        ui: &mut egui::Ui,
+        runtime: &Runtime,
+        config: &mut Config,
+        selected_metadata: &CompleteVersionMetadata,
+        instance_storage: &mut InstanceStorage,
+    ) -> bool {
+        let lang = config.lang;
+        let version_name = selected_metadata.get_name();
+        let channels = instance_storage.get_channels(version_name);
+        if channels.len() < 2 {
+            return false;
+        }
+
+        let channel_text = |channel: &Option<String>| match channel {
+            Some(channel) => channel.clone(),
+            None => LangMessage::DefaultChannel.to_string(lang),
+        };
+        let old_channel = instance_storage.get_channel(version_name);
+        let mut selected_channel = old_channel.clone();
+        ui.label(LangMessage::Channel.to_string(lang));
+        egui::ComboBox::from_id_salt("instance_channel")
+            .selected_text(channel_text(&selected_channel))
+            .show_ui(ui, |ui| {
+                for channel in &channels {
+                    ui.selectable_value(
+                        &mut selected_channel,
+                        channel.clone(),
+                        channel_text(channel),
+                    );
+                }
+            });
+
+        if selected_channel != old_channel {
+            runtime.block_on(instance_storage.set_channel(config, version_name, selected_channel));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn render_build_selector(
+        &mut self,
+        ui: &mut egui::Ui,
+        runtime: &Runtime,
+        config: &mut Config,
+        selected_metadata: &CompleteVersionMetadata,
+        instance_storage: &mut InstanceStorage,
+    ) -> bool {
+        let lang = config.lang;
+        let version_name = selected_metadata.get_name();
+        let builds = instance_storage.get_builds(version_name);
+        if builds.len() < 2 {
+            return false;
+        }
+
+        let build_text = |build: &Option<u64>| match build {
+            Some(built_at) => utils::format_timestamp(*built_at),
+            None => LangMessage::LatestBuild.to_string(lang),
+        };
+        let old_build = instance_storage.get_pinned_build(version_name);
+        let mut selected_build = old_build;
+        ui.label(LangMessage::Build.to_string(lang));
+        egui::ComboBox::from_id_salt("instance_build")
+            .selected_text(build_text(&selected_build))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut selected_build, None, build_text(&None));
+                for built_at in builds {
+                    ui.selectable_value(
+                        &mut selected_build,
+                        Some(built_at),
+                        build_text(&Some(built_at)),
+                    );
+                }
+            });
+
+        if selected_build != old_build {
+            runtime.block_on(instance_storage.set_pinned_build(
+                config,
+                version_name,
+                selected_build,
+            ));
+            true
+        } else {
+            false
         }
     }
 
@@ -301,9 +454,11 @@ impl SettingsState {
         runtime: &Runtime,
         config: &mut Config,
         selected_metadata: &CompleteVersionMetadata,
-    ) {
+        instance_storage: &mut InstanceStorage,
+    ) -> InstanceSettingsResult {
         let lang = config.lang;
         let mut settings_opened = self.instance_settings_opened;
+        let mut result = InstanceSettingsResult::default();
 
         egui::Window::new(LangMessage::InstanceSettings.to_string(lang))
             .open(&mut settings_opened)
@@ -369,9 +524,32 @@ impl SettingsState {
 
                 #[cfg(target_os = "linux")]
                 self.render_use_native_glfw_checkbox(ui, config, selected_metadata);
+
+                result.files_changed = self.render_optional_groups(
+                    ui,
+                    runtime,
+                    config,
+                    selected_metadata,
+                    instance_storage,
+                );
+                result.build_changed = self.render_channel_selector(
+                    ui,
+                    runtime,
+                    config,
+                    selected_metadata,
+                    instance_storage,
+                );
+                result.build_changed |= self.render_build_selector(
+                    ui,
+                    runtime,
+                    config,
+                    selected_metadata,
+                    instance_storage,
+                );
             });
 
         self.instance_settings_opened = settings_opened;
+        result
     }
 
     fn render_close_launcher_checkbox(&mut self, ui: &mut egui::Ui, config: &mut Config) {
@@ -12,6 +12,10 @@ pub fn get_default_version_manifest_url() -> String {
     VERSION_MANIFEST_URL.to_string()
 }
 
+pub fn get_trusted_manifest_keys() -> &'static [&'static str] {
+    TRUSTED_MANIFEST_KEYS
+}
+
 pub fn get_backend_api_base() -> Option<String> {
     BACKEND_API_BASE.map(|url| url.trim_end_matches('/').to_string())
 }
@@ -32,6 +32,9 @@ pub struct Config {
     pub extra_version_manifest_urls: Vec<String>,
     #[serde(default = "provide_default_version_manifest_url")]
     pub selected_version_manifest_url: String,
+    // instance name -> optional group id -> enabled
+    #[serde(default)]
+    pub optional_groups: HashMap<String, HashMap<String, bool>>,
 }
 
 const CONFIG_FILENAME: &str = "config.json";
@@ -63,6 +66,7 @@ impl Config {
             auth_profiles: HashMap::new(),
             extra_version_manifest_urls: Vec::new(),
             selected_version_manifest_url: build_config::get_default_version_manifest_url(),
+            optional_groups: HashMap::new(),
         }
     }
 
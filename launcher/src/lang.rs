@@ -24,6 +24,7 @@ pub enum LangMessage {
     NoInstances,
     CheckingFiles,
     DownloadingFiles,
+    TimeLeft(String),
     SyncInstance,
     InstanceNotSynced,
     InstanceSynced,
@@ -45,6 +46,11 @@ pub enum LangMessage {
     SelectedJavaPath,
     NoJavaPath,
     AllocatedMemory,
+    Channel,
+    DefaultChannel,
+    Build,
+    LatestBuild,
+    OptionalGroups,
     SelectJavaPath,
     #[cfg(target_os = "linux")]
     UseNativeGlfw,
@@ -90,6 +96,10 @@ pub enum LangMessage {
     SelectInstanceToDelete,
     ConfirmDelete,
     Delete,
+    ExportInstance,
+    ExportingInstance,
+    InstanceExported,
+    InstanceExportError,
     AddAccount,
     SelectAccount,
     AddAndAuthenticate,
@@ -169,6 +179,10 @@ impl LangMessage {
                 Lang::English => "Downloading files...".to_string(),
                 Lang::Russian => "Загрузка файлов...".to_string(),
             },
+            LangMessage::TimeLeft(time) => match lang {
+                Lang::English => format!("{time} left"),
+                Lang::Russian => format!("осталось {time}"),
+            },
             LangMessage::SyncInstance => match lang {
                 Lang::English => "Sync instance".to_string(),
                 Lang::Russian => "Синхронизировать версию".to_string(),
@@ -237,6 +251,26 @@ impl LangMessage {
                 Lang::English => "Allocated memory".to_string(),
                 Lang::Russian => "Выделенная память".to_string(),
             },
+            LangMessage::Channel => match lang {
+                Lang::English => "Channel".to_string(),
+                Lang::Russian => "Канал".to_string(),
+            },
+            LangMessage::DefaultChannel => match lang {
+                Lang::English => "Stable".to_string(),
+                Lang::Russian => "Стабильный".to_string(),
+            },
+            LangMessage::Build => match lang {
+                Lang::English => "Build".to_string(),
+                Lang::Russian => "Сборка".to_string(),
+            },
+            LangMessage::LatestBuild => match lang {
+                Lang::English => "Latest".to_string(),
+                Lang::Russian => "Последняя".to_string(),
+            },
+            LangMessage::OptionalGroups => match lang {
+                Lang::English => "Optional files".to_string(),
+                Lang::Russian => "Дополнительные файлы".to_string(),
+            },
             LangMessage::SelectJavaPath => match lang {
                 Lang::English => "Select Java path".to_string(),
                 Lang::Russian => "Выберите путь к Java".to_string(),
@@ -427,6 +461,22 @@ impl LangMessage {
                 Lang::English => "Delete".to_string(),
                 Lang::Russian => "Удалить".to_string(),
             },
+            LangMessage::ExportInstance => match lang {
+                Lang::English => "Export as a Modrinth modpack (.mrpack)".to_string(),
+                Lang::Russian => "Экспортировать как модпак Modrinth (.mrpack)".to_string(),
+            },
+            LangMessage::ExportingInstance => match lang {
+                Lang::English => "Exporting...".to_string(),
+                Lang::Russian => "Экспорт...".to_string(),
+            },
+            LangMessage::InstanceExported => match lang {
+                Lang::English => "Instance exported".to_string(),
+                Lang::Russian => "Версия экспортирована".to_string(),
+            },
+            LangMessage::InstanceExportError => match lang {
+                Lang::English => "Error exporting instance".to_string(),
+                Lang::Russian => "Ошибка при экспорте версии".to_string(),
+            },
             LangMessage::AddAccount => match lang {
                 Lang::English => "Add account".to_string(),
                 Lang::Russian => "Добавить аккаунт".to_string(),
@@ -124,3 +124,14 @@ pub fn format_xmx(xmx: Option<&str>) -> String {
 
     format!("{xmx_mb}M")
 }
+
+// unix timestamp in seconds -> local date and time
+pub fn format_timestamp(timestamp: u64) -> String {
+    match chrono::DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(time) => time
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => timestamp.to_string(),
+    }
+}
@@ -4,7 +4,7 @@ use shared::{
     paths::{get_client_jar_path, get_versions_dir, get_versions_extra_dir},
     progress,
     version::{
-        extra_version_metadata::{AuthBackend, ExtraVersionMetadata},
+        extra_version_metadata::{AuthBackend, ExtraVersionMetadata, JavaRuntime},
         version_manifest::VersionInfo,
         version_metadata::{Arguments, AssetIndex, Library, VersionMetadata},
     },
@@ -196,4 +196,15 @@ impl CompleteVersionMetadata {
             .as_ref()
             .and_then(|extra| extra.recommended_xmx.as_deref())
     }
+
+    // java for this platform hosted on the download server of the instance
+    pub fn get_java_runtime(&self) -> Option<&JavaRuntime> {
+        self.extra.as_ref().and_then(|extra| {
+            extra.get_java_runtime(
+                &self.get_java_version(),
+                &os::get_os_name(),
+                &os::get_system_arch(),
+            )
+        })
+    }
 }
@@ -28,6 +28,14 @@ pub struct LocalInstance {
     pub version_info: VersionInfo,
     pub status: InstanceStatus,
     pub manifest_url: Option<String>,
+    #[serde(default)]
+    pub channel: Option<String>,
+    // build timestamp the instance is kept at instead of updating to the latest one
+    #[serde(default)]
+    pub pinned_build: Option<u64>,
+    // instance settings that affect the files were changed since the last sync
+    #[serde(default)]
+    pub pending_sync: bool,
 }
 
 pub struct InstanceStorage {
@@ -193,6 +201,150 @@ impl InstanceStorage {
         }
     }
 
+    fn get_local_instance(&self, version_name: &str) -> Option<&LocalInstance> {
+        self.instances
+            .iter()
+            .find(|instance| instance.version_info.get_name() == version_name)
+    }
+
+    fn get_selected_channel(&self, version_name: &str) -> Option<String> {
+        self.get_local_instance(version_name)
+            .and_then(|instance| instance.channel.clone())
+    }
+
+    // falls back to the default channel if the selected one is no longer published,
+    // and to any channel if there is no default one
+    fn get_remote_channel_version(&self, version_name: &str) -> Option<&VersionInfo> {
+        let channel = self.get_selected_channel(version_name);
+        let mut remote_versions = self.get_remote_versions();
+        remote_versions.retain(|x| x.get_name() == version_name);
+        let find_channel = |channel: &Option<String>| {
+            remote_versions
+                .iter()
+                .find(|x| &x.channel == channel)
+                .copied()
+        };
+        find_channel(&channel)
+            .or_else(|| find_channel(&None))
+            .or_else(|| remote_versions.first().copied())
+    }
+
+    // the pinned build if it is still published, the latest one otherwise
+    fn get_remote_version(&self, version_name: &str) -> Option<VersionInfo> {
+        let remote_version = self.get_remote_channel_version(version_name)?;
+        let pinned_build = self
+            .get_local_instance(version_name)
+            .and_then(|instance| instance.pinned_build);
+        pinned_build
+            .and_then(|built_at| remote_version.get_build(built_at))
+            .or_else(|| Some(remote_version.without_history()))
+    }
+
+    /// Channels the remote manifest publishes for the instance, the default one first.
+    pub fn get_channels(&self, version_name: &str) -> Vec<Option<String>> {
+        let mut channels: Vec<Option<String>> = self
+            .get_remote_versions()
+            .into_iter()
+            .filter(|x| x.get_name() == version_name)
+            .map(|x| x.channel.clone())
+            .collect();
+        channels.sort();
+        channels.dedup();
+        channels
+    }
+
+    pub fn get_channel(&self, version_name: &str) -> Option<String> {
+        self.get_remote_channel_version(version_name)
+            .and_then(|x| x.channel.clone())
+    }
+
+    /// Build timestamps of the selected channel that can be pinned, newest first.
+    pub fn get_builds(&self, version_name: &str) -> Vec<u64> {
+        match self.get_remote_channel_version(version_name) {
+            Some(remote_version) => remote_version
+                .built_at
+                .into_iter()
+                .chain(remote_version.history.iter().map(|build| build.built_at))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get_pinned_build(&self, version_name: &str) -> Option<u64> {
+        self.get_local_instance(version_name)
+            .and_then(|instance| instance.pinned_build)
+            .filter(|built_at| self.get_builds(version_name).contains(built_at))
+    }
+
+    async fn update_local_instance(
+        &mut self,
+        config: &Config,
+        version_name: &str,
+        update: impl FnOnce(&mut LocalInstance),
+    ) {
+        if self.get_local_instance(version_name).is_none() {
+            let Some(remote_version) = self.get_remote_channel_version(version_name) else {
+                warn!("Tried to update non-existent version: {version_name}");
+                return;
+            };
+            // remember the choice for an instance that hasn't been downloaded yet
+            let version_info = remote_version.without_history();
+            self.instances.push(LocalInstance {
+                version_info,
+                status: InstanceStatus::Missing,
+                manifest_url: self.remote_manifest_url.clone(),
+                channel: None,
+                pinned_build: None,
+                pending_sync: false,
+            });
+        }
+        if let Some(instance) = self
+            .instances
+            .iter_mut()
+            .find(|instance| instance.version_info.get_name() == version_name)
+        {
+            update(instance);
+        }
+        self.safe_save(config).await;
+    }
+
+    pub async fn set_channel(
+        &mut self,
+        config: &Config,
+        version_name: &str,
+        channel: Option<String>,
+    ) {
+        self.update_local_instance(config, version_name, |instance| {
+            instance.channel = channel;
+            // builds of different channels are unrelated
+            instance.pinned_build = None;
+        })
+        .await;
+    }
+
+    pub async fn set_pinned_build(
+        &mut self,
+        config: &Config,
+        version_name: &str,
+        pinned_build: Option<u64>,
+    ) {
+        self.update_local_instance(config, version_name, |instance| {
+            instance.pinned_build = pinned_build;
+        })
+        .await;
+    }
+
+    pub async fn mark_pending_sync(&mut self, config: &Config, version_name: &str) {
+        if let Some(instance) = self
+            .instances
+            .iter_mut()
+            .find(|instance| instance.version_info.get_name() == version_name)
+        {
+            instance.pending_sync = true;
+            self.safe_save(config).await;
+        }
+    }
+
     pub fn get_all_names_for_manifest_url(&self, url: &str) -> (Vec<String>, Vec<String>) {
         let local_names: HashSet<String> = self
             .instances
@@ -200,12 +352,13 @@ impl InstanceStorage {
             .filter(|instance| instance.manifest_url.as_deref().unwrap_or(url) == url)
             .map(|x| x.version_info.get_name())
             .collect();
-        let remote_names: Vec<String> = self
-            .get_remote_versions()
-            .iter()
-            .map(|x| x.get_name())
-            .filter(|x| !local_names.contains(x))
-            .collect();
+        let mut remote_names: Vec<String> = Vec::new();
+        for name in self.get_remote_versions().iter().map(|x| x.get_name()) {
+            // channels of one instance share the name
+            if !local_names.contains(&name) && !remote_names.contains(&name) {
+                remote_names.push(name);
+            }
+        }
 
         let mut local_names: Vec<String> = local_names.into_iter().collect();
         local_names.sort();
@@ -225,16 +378,23 @@ impl InstanceStorage {
             version_info,
             status: InstanceStatus::Outdated,
             manifest_url: None,
+            channel: None,
+            pinned_build: None,
+            pending_sync: false,
         });
         self.safe_save(config).await;
     }
 
     pub fn get_instance(&self, version_name: &str) -> Option<LocalInstance> {
         let local_instance = self
-            .instances
-            .iter()
-            .find(|instance| instance.version_info.get_name() == version_name)
-            .cloned();
+            .get_local_instance(version_name)
+            .cloned()
+            .map(|mut instance| {
+                if instance.pending_sync && instance.status == InstanceStatus::UpToDate {
+                    instance.status = InstanceStatus::Outdated;
+                }
+                instance
+            });
         if let Some(local_instance) = &local_instance
             && let Some(manifest_url) = self.remote_manifest_url.clone()
             && let Some(instance_manifest_url) = local_instance.manifest_url.clone()
@@ -242,16 +402,16 @@ impl InstanceStorage {
         {
             return Some(local_instance.clone()); // TODO: allow different manifest urls for the same instance name
         }
-        let remote_version_info = self
-            .get_remote_versions()
-            .into_iter()
-            .find(|x| x.get_name() == version_name);
+        let remote_version_info = self.get_remote_version(version_name);
 
         if let Some(remote_version_info) = remote_version_info {
             let remote_instance = LocalInstance {
                 version_info: remote_version_info.clone(),
-                status: if let Some(instance) = local_instance {
-                    if remote_version_info != &instance.version_info {
+                status: if let Some(instance) = &local_instance {
+                    if instance.status == InstanceStatus::Missing {
+                        InstanceStatus::Missing
+                    } else if instance.pending_sync || remote_version_info != instance.version_info
+                    {
                         InstanceStatus::Outdated
                     } else {
                         InstanceStatus::UpToDate
@@ -260,6 +420,13 @@ impl InstanceStorage {
                     InstanceStatus::Missing
                 },
                 manifest_url: self.remote_manifest_url.clone(),
+                channel: local_instance
+                    .as_ref()
+                    .and_then(|instance| instance.channel.clone()),
+                pinned_build: local_instance
+                    .as_ref()
+                    .and_then(|instance| instance.pinned_build),
+                pending_sync: local_instance.is_some_and(|instance| instance.pending_sync),
             };
             Some(remote_instance)
         } else {
@@ -268,19 +435,22 @@ impl InstanceStorage {
     }
 
     pub async fn mark_downloaded(&mut self, config: &Config, version_name: &str) {
-        let remote_versions = self.get_remote_versions();
-        let remote_version = remote_versions
-            .into_iter()
-            .find(|v| v.get_name() == version_name)
-            .cloned();
+        let remote_version = self.get_remote_version(version_name);
 
         if let Some(remote_version) = remote_version {
+            let channel = remote_version.channel.clone();
+            let pinned_build = self
+                .get_local_instance(version_name)
+                .and_then(|instance| instance.pinned_build);
             self.instances
                 .retain(|instance| instance.version_info.get_name() != version_name);
             self.instances.push(LocalInstance {
                 version_info: remote_version,
                 status: InstanceStatus::UpToDate,
                 manifest_url: self.remote_manifest_url.clone(),
+                channel,
+                pinned_build,
+                pending_sync: false,
             });
             self.safe_save(config).await;
         } else if let Some(instance) = self
@@ -289,6 +459,7 @@ impl InstanceStorage {
             .find(|instance| instance.version_info.get_name() == version_name)
         {
             instance.status = InstanceStatus::UpToDate;
+            instance.pending_sync = false;
             self.safe_save(config).await;
         } else {
             warn!("Tried to mark non-existent version as downloaded: {version_name}");
@@ -8,8 +8,10 @@ use rand::SeedableRng as _;
 use rand::rngs::StdRng;
 use rand::seq::SliceRandom as _;
 use shared::adaptive_download::download_files;
+use shared::hash_cache::HashCache;
 use shared::paths::{
-    get_authlib_injector_path, get_libraries_dir, get_minecraft_dir, get_natives_dir,
+    get_authlib_injector_path, get_hash_cache_path, get_libraries_dir, get_minecraft_dir,
+    get_natives_dir,
 };
 use shared::version::asset_metadata::AssetsMetadata;
 use std::fs;
@@ -32,14 +34,37 @@ async fn get_objects_entries(
     extra_version_metadata: &ExtraVersionMetadata,
     force_overwrite: bool,
     minecraft_dir: &Path,
+    optional_groups: &HashMap<String, bool>,
 ) -> anyhow::Result<Vec<CheckEntry>> {
-    let include = &extra_version_metadata.include;
+    let os_name = os::get_os_name();
+    let arch = os::get_system_arch();
+    let include = extra_version_metadata
+        .include
+        .iter()
+        .filter(|rule| rule.applies_to_os(&os_name, &arch));
 
     let mut check_entries = vec![];
     let mut used_paths = HashSet::new();
     for rule in include {
         let objects = &rule.objects;
 
+        if let Some(group) = &rule.optional_group
+            && !optional_groups
+                .get(&group.id)
+                .copied()
+                .unwrap_or(group.enabled_by_default)
+        {
+            // files of a disabled group are removed, not kept as user files
+            for object in objects {
+                let path = minecraft_dir.join(&object.path);
+                if path.is_file() {
+                    tokio_fs::remove_file(&path).await?;
+                }
+                used_paths.insert(path);
+            }
+            continue;
+        }
+
         let objects_paths = rule
             .objects
             .iter()
@@ -48,9 +73,14 @@ async fn get_objects_entries(
 
         if rule.overwrite && rule.delete_extra || force_overwrite {
             let rule_path = minecraft_dir.join(&rule.path);
+            let path_filter = rule.get_path_filter()?;
             let files_in_dir = files::get_files_ignore_paths(&rule_path, &used_paths)?;
             for file in files_in_dir {
-                if !objects_paths.contains(&file) {
+                // files excluded from the rule are not managed by it
+                let filtered_out = file
+                    .strip_prefix(minecraft_dir)
+                    .is_ok_and(|rel_path| !path_filter.matches(&rel_path.to_string_lossy()));
+                if !objects_paths.contains(&file) && !filtered_out {
                     tokio_fs::remove_file(file).await?;
                 }
             }
@@ -61,6 +91,7 @@ async fn get_objects_entries(
                 url: object.url.clone(),
                 remote_sha1: Some(object.sha1.clone()),
                 path: minecraft_dir.join(&object.path),
+                size: object.size,
             }));
         } else if rule.recursive
             || !minecraft_dir.join(&rule.path).exists()
@@ -76,6 +107,7 @@ async fn get_objects_entries(
                         url: object.url.clone(),
                         remote_sha1: Some(object.sha1.clone()),
                         path,
+                        size: object.size,
                     })
                 } else {
                     None
@@ -247,6 +279,7 @@ fn get_authlib_injector_entry(
             url: AUTHLIB_INJECTOR_URL.to_string(),
             remote_sha1: Some(AUTHLIB_INJECTOR_SHA1.to_string()),
             path: get_authlib_injector_path(launcher_dir),
+            size: None,
         })
     }
 }
@@ -274,6 +307,7 @@ pub async fn sync_instance(
     force_overwrite: bool,
     launcher_dir: &Path,
     assets_dir: &Path,
+    optional_groups: &HashMap<String, bool>,
     progress_bar: Arc<dyn ProgressBar<LangMessage> + Send + Sync>,
 ) -> anyhow::Result<()> {
     let version_name = version_metadata.get_name();
@@ -291,7 +325,9 @@ pub async fn sync_instance(
     check_entries.extend(get_libraries_entries(&libraries, &libraries_dir).await?);
 
     if let Some(extra) = version_metadata.get_extra() {
-        check_entries.extend(get_objects_entries(extra, force_overwrite, &minecraft_dir).await?);
+        check_entries.extend(
+            get_objects_entries(extra, force_overwrite, &minecraft_dir, optional_groups).await?,
+        );
     }
 
     if let Some(authlib_injector) = get_authlib_injector_entry(version_metadata, launcher_dir) {
@@ -309,8 +345,17 @@ pub async fn sync_instance(
 
     info!("Got {} check download entries", check_entries.len());
     progress_bar.set_message(LangMessage::CheckingFiles);
-    let mut download_entries =
-        files::get_download_entries(check_entries, progress_bar.clone()).await?;
+    // force overwrite doesn't trust the cache and hashes everything again
+    let hash_cache_path = get_hash_cache_path(launcher_dir);
+    let mut hash_cache = HashCache::load(&hash_cache_path).await;
+    let mut download_entries = files::get_download_entries_cached(
+        check_entries,
+        &mut hash_cache,
+        force_overwrite,
+        progress_bar.clone(),
+    )
+    .await?;
+    hash_cache.save(&hash_cache_path).await?;
 
     let mut rng = StdRng::from_os_rng();
     download_entries.shuffle(&mut rng);
@@ -323,9 +368,22 @@ pub async fn sync_instance(
         .collect::<Vec<_>>();
     debug!("Paths to download: {paths:?}");
 
+    let downloaded_hashes = download_entries
+        .iter()
+        .filter_map(|entry| Some((entry.path.clone(), entry.sha1.clone()?)))
+        .collect::<Vec<_>>();
+
     progress_bar.set_message(LangMessage::DownloadingFiles);
     download_files(download_entries, progress_bar).await?;
 
+    // downloaded files are verified against their hashes, no need to hash them next time
+    for (path, sha1) in downloaded_hashes {
+        hash_cache.insert(&path, sha1);
+    }
+    // drop the files of deleted instances and removed mods, so the cache doesn't grow forever
+    hash_cache.prune();
+    hash_cache.save(&hash_cache_path).await?;
+
     extract_natives(&libraries, &libraries_dir, &natives_dir)?;
 
     mark_download_complete(version_metadata, &minecraft_dir).await?;
@@ -1,6 +1,7 @@
 use futures::stream::{FuturesUnordered, StreamExt};
 use log::{debug, warn};
 use reqwest::Client;
+use sha1::{Digest, Sha1};
 use std::collections::VecDeque;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
@@ -11,8 +12,9 @@ use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 
 use crate::files::{self, DownloadEntry};
-use crate::progress::ProgressBar;
-use crate::utils::is_connect_error;
+use crate::partial_download::{self, PartialDownload};
+use crate::progress::{ProgressBar, Unit};
+use crate::utils::{is_connect_error, KeyedLocks};
 
 const MAX_CONCURRENCY: usize = 50;
 const MIN_CONCURRENCY: usize = 1;
@@ -20,6 +22,7 @@ const WINDOW_DURATION: Duration = Duration::from_secs(2);
 const UPDATE_CONCURRENCY_EVERY: usize = 5;
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(4);
 const MAX_TIMEOUTS_AT_MIN_CONCURRENCY: usize = 2;
+const MAX_HASH_MISMATCH_RETRIES: usize = 3;
 
 struct DownloadRecord {
     timestamp: Instant,
@@ -96,35 +99,107 @@ impl SlidingWindow {
     }
 }
 
-async fn download_file(client: &Client, entry: &DownloadEntry) -> anyhow::Result<u128> {
-    let start = Instant::now();
+// byte progress of a single file, retries don't count the same bytes twice.
+// the file counts as `expected` bytes, its listed size or an estimate
+struct FileProgress<'a, M> {
+    progress_bar: &'a (dyn ProgressBar<M> + Send + Sync),
+    reported: u64,
+    expected: u64,
+}
 
-    let response = client.get(&entry.url).send().await?.error_for_status()?;
-    let mut stream = response.bytes_stream();
+impl<M> FileProgress<'_, M> {
+    fn update(&mut self, downloaded: u64) {
+        let downloaded = downloaded.min(self.expected);
+        if downloaded > self.reported {
+            self.progress_bar.inc(downloaded - self.reported);
+            self.reported = downloaded;
+        }
+    }
 
-    if let Some(parent_dir) = entry.path.parent() {
-        tokio::fs::create_dir_all(parent_dir).await?;
+    fn finish(&mut self) {
+        self.update(self.expected);
     }
+}
 
-    // write to a temporary file first
-    let mut tmp_path = entry.path.as_os_str().to_owned();
-    tmp_path.push(".tmp");
-    let tmp_path = std::path::PathBuf::from(tmp_path);
+// the bytes each entry counts for in the progress, files without a listed size
+// count as an average one, `None` if no sizes are known and files are counted instead
+fn get_progress_sizes(download_entries: &[DownloadEntry]) -> Option<Vec<u64>> {
+    let known_sizes: Vec<u64> = download_entries
+        .iter()
+        .filter_map(|entry| entry.size)
+        .collect();
+    let known_total: u64 = known_sizes.iter().sum();
+    if known_total == 0 {
+        return None;
+    }
+    let average_size = known_total / known_sizes.len() as u64;
+    Some(
+        download_entries
+            .iter()
+            .map(|entry| entry.size.unwrap_or(average_size))
+            .collect(),
+    )
+}
 
-    {
-        let mut file = tokio::fs::File::create(&tmp_path)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to create temp file {:?}: {}", tmp_path, e))?;
+lazy_static::lazy_static! {
+    // parallel builds can download the same file, e.g. libraries shared by minecraft versions,
+    // they must not write to the same temporary file at once
+    static ref DOWNLOAD_LOCKS: KeyedLocks = KeyedLocks::default();
+}
+
+async fn download_file<M>(
+    client: &Client,
+    entry: &DownloadEntry,
+    progress: &mut Option<FileProgress<'_, M>>,
+) -> anyhow::Result<u128> {
+    let _guard = DOWNLOAD_LOCKS.lock(&entry.path.to_string_lossy()).await;
+    let start = Instant::now();
 
+    // write to a temporary file first, a partial one is resumed if possible
+    let tmp_path = partial_download::get_tmp_path(&entry.path);
+    let PartialDownload {
+        response,
+        mut file,
+        offset,
+    } = partial_download::start_download(client, &entry.url, &tmp_path).await?;
+
+    let mut hasher = Sha1::new();
+    if offset > 0 {
+        partial_download::read_downloaded_part(&tmp_path, |chunk| hasher.update(chunk)).await?;
+    }
+
+    let mut downloaded = offset;
+    let mut stream = response.bytes_stream();
+    {
         let per_chunk_timeout = REQUEST_TIMEOUT;
         while let Some(chunk_result) =
             tokio::time::timeout(per_chunk_timeout, stream.next()).await?
         {
             let chunk = chunk_result?;
+            hasher.update(&chunk);
             file.write_all(&chunk).await?;
+            downloaded += chunk.len() as u64;
+            if let Some(progress) = progress {
+                progress.update(downloaded);
+            }
         }
         file.flush().await?;
     }
+    drop(file);
+
+    if let Some(expected) = &entry.sha1 {
+        let actual = format!("{:x}", hasher.finalize());
+        if !expected.trim().eq_ignore_ascii_case(&actual) {
+            partial_download::discard_download(&tmp_path).await;
+            return Err(AdaptiveDownloadError::HashMismatch {
+                url: entry.url.clone(),
+                expected: expected.trim().to_string(),
+                actual,
+            }
+            .into());
+        }
+    }
+    partial_download::finish_download(&tmp_path).await;
 
     if !tmp_path.exists() {
         return Err(anyhow::anyhow!(
@@ -157,20 +232,39 @@ fn is_timeout_error(e: &anyhow::Error) -> bool {
     // reqwest doesn't let us check for this error directly
 }
 
+fn is_hash_mismatch_error(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<AdaptiveDownloadError>(),
+        Some(AdaptiveDownloadError::HashMismatch { .. })
+    )
+}
+
 /// Download a single file, returning (success, latency_ms).
 /// On success, we return Ok(Some(latency_ms)).
 /// If it's a timeout, we return Ok(None). If it's another error, we return Err(e).
-async fn do_download(client: &Client, entry: &DownloadEntry) -> anyhow::Result<Option<u128>> {
-    let latency_ms = match download_file(client, entry).await {
-        Ok(r) => r,
-        Err(e) => {
-            // If it's a timeout, we return Ok(None), else Err
-            if is_timeout_error(&e) || is_connect_error(&e) {
-                debug!("Timeout downloading {}", entry.url);
-                return Ok(None);
-            } else {
-                debug!("Error downloading {}: {:?}", entry.url, e);
-                return Err(e);
+/// Files with a mismatching hash are re-downloaded a few times before giving up.
+async fn do_download<M>(
+    client: &Client,
+    entry: &DownloadEntry,
+    progress: &mut Option<FileProgress<'_, M>>,
+) -> anyhow::Result<Option<u128>> {
+    let mut hash_mismatches = 0;
+    let latency_ms = loop {
+        match download_file(client, entry, progress).await {
+            Ok(r) => break r,
+            Err(e) => {
+                // If it's a timeout, we return Ok(None), else Err
+                if is_timeout_error(&e) || is_connect_error(&e) {
+                    debug!("Timeout downloading {}", entry.url);
+                    return Ok(None);
+                } else if is_hash_mismatch_error(&e) && hash_mismatches < MAX_HASH_MISMATCH_RETRIES
+                {
+                    hash_mismatches += 1;
+                    warn!("{e}, retrying ({hash_mismatches}/{MAX_HASH_MISMATCH_RETRIES})");
+                } else {
+                    debug!("Error downloading {}: {:?}", entry.url, e);
+                    return Err(e);
+                }
             }
         }
     };
@@ -182,13 +276,30 @@ async fn do_download(client: &Client, entry: &DownloadEntry) -> anyhow::Result<O
 pub enum AdaptiveDownloadError {
     #[error("Connection timed out")]
     ConnectionTimeout,
+    #[error("Hash mismatch for {url}: expected {expected}, got {actual}")]
+    HashMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
 }
 
 pub async fn download_files<M>(
     download_entries: Vec<DownloadEntry>,
     progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
 ) -> anyhow::Result<()> {
-    progress_bar.set_length(download_entries.len() as u64);
+    // show progress in bytes if file sizes are known, otherwise count files
+    let progress_sizes = get_progress_sizes(&download_entries);
+    if let Some(progress_sizes) = &progress_sizes {
+        progress_bar.set_unit(Unit {
+            name: "MB".to_string(),
+            size: 1024 * 1024,
+        });
+        progress_bar.set_length(progress_sizes.iter().sum());
+    } else {
+        progress_bar.clear_unit();
+        progress_bar.set_length(download_entries.len() as u64);
+    }
 
     let client = Client::builder().connect_timeout(REQUEST_TIMEOUT).build()?;
 
@@ -196,7 +307,18 @@ pub async fn download_files<M>(
 
     let sliding_window = Arc::new(Mutex::new(SlidingWindow::new()));
 
-    let mut cur_entries = download_entries;
+    let mut cur_entries = download_entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let progress = progress_sizes.as_ref().map(|sizes| FileProgress {
+                progress_bar: progress_bar.as_ref(),
+                reported: 0,
+                expected: sizes[i],
+            });
+            (entry, progress)
+        })
+        .collect::<Vec<_>>();
     let mut active = FuturesUnordered::new();
 
     fn can_spawn_more(active_count: usize, concurrency: &Arc<AtomicUsize>) -> bool {
@@ -205,10 +327,11 @@ pub async fn download_files<M>(
 
     let spawn_if_possible = |active: &mut FuturesUnordered<_>, cur_entries: &mut Vec<_>| {
         while can_spawn_more(active.len(), &desired_concurrency) {
-            if let Some(entry) = cur_entries.pop() {
-                let fut = async {
-                    let result = do_download(&client, &entry).await;
-                    (result, entry)
+            if let Some((entry, mut progress)) = cur_entries.pop() {
+                let client = &client;
+                let fut = async move {
+                    let result = do_download(client, &entry, &mut progress).await;
+                    (result, entry, progress)
                 };
                 active.push(fut);
             } else {
@@ -223,17 +346,21 @@ pub async fn download_files<M>(
 
     let mut next_concurrency_update = UPDATE_CONCURRENCY_EVERY;
     loop {
-        let Some((result, entry)) = active.next().await else {
+        let Some((result, entry, progress)) = active.next().await else {
             break;
         };
 
         let (success, latency_ms) = match result {
             Ok(Some(latency_ms)) => {
-                progress_bar.inc(1);
+                match progress {
+                    // the listed size may be off, count the file as complete anyway
+                    Some(mut progress) => progress.finish(),
+                    None => progress_bar.inc(1),
+                }
                 (true, latency_ms)
             }
             Ok(None) => {
-                cur_entries.push(entry);
+                cur_entries.push((entry, progress));
                 (false, 0)
             }
             Err(e) => {
@@ -280,3 +407,129 @@ pub async fn download_files<M>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use tokio::io::AsyncReadExt as _;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::progress::no_progress_bar;
+
+    fn entry(size: Option<u64>) -> DownloadEntry {
+        DownloadEntry {
+            url: String::new(),
+            path: std::path::PathBuf::new(),
+            sha1: None,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_get_progress_sizes() {
+        let entries = [entry(Some(100)), entry(None), entry(Some(300))];
+        assert_eq!(get_progress_sizes(&entries), Some(vec![100, 200, 300]));
+
+        assert_eq!(get_progress_sizes(&[entry(None), entry(None)]), None);
+        assert_eq!(get_progress_sizes(&[entry(Some(0))]), None);
+        assert_eq!(get_progress_sizes(&[]), None);
+    }
+
+    // serves `body` with a strong etag, honoring "Range: bytes=N-" requests
+    async fn serve_body(body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let n = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_lowercase();
+                let range_start = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+                let header = match range_start {
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                        start,
+                        body.len() - 1,
+                        body.len(),
+                        body.len() - start
+                    ),
+                    None => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    ),
+                };
+                let _ = socket.write_all(header.as_bytes()).await;
+                let _ = socket.write_all(&body[range_start.unwrap_or(0)..]).await;
+            }
+        });
+        format!("http://{addr}/file")
+    }
+
+    #[tokio::test]
+    async fn test_download_verifies_hash() {
+        let url = serve_body(b"hello").await;
+        let temp_dir = env::temp_dir().join("adaptive_download_hash_test");
+        let good_path = temp_dir.join("good");
+        let bad_path = temp_dir.join("bad");
+
+        let entries = vec![DownloadEntry {
+            url: url.clone(),
+            path: good_path.clone(),
+            sha1: Some("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d".to_string()),
+            size: None,
+        }];
+        download_files(entries, no_progress_bar()).await.unwrap();
+        assert_eq!(tokio::fs::read(&good_path).await.unwrap(), b"hello");
+
+        let entries = vec![DownloadEntry {
+            url,
+            path: bad_path.clone(),
+            sha1: Some("0000000000000000000000000000000000000000".to_string()),
+            size: None,
+        }];
+        let err = download_files(entries, no_progress_bar())
+            .await
+            .unwrap_err();
+        assert!(is_hash_mismatch_error(&err));
+        assert!(!bad_path.exists());
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_partial_file() {
+        let url = serve_body(b"hello").await;
+        let temp_dir = env::temp_dir().join("adaptive_download_resume_test");
+        let path = temp_dir.join("file");
+        let tmp_path = partial_download::get_tmp_path(&path);
+
+        // pretend that an earlier download was interrupted after 3 bytes
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        tokio::fs::write(&tmp_path, b"HEL").await.unwrap();
+        let resume_info = serde_json::json!({ "url": url, "validator": "\"v1\"" });
+        tokio::fs::write(
+            temp_dir.join("file.tmp.resume"),
+            serde_json::to_vec(&resume_info).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let entries = vec![DownloadEntry {
+            url,
+            path: path.clone(),
+            sha1: None,
+            size: None,
+        }];
+        download_files(entries, no_progress_bar()).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"HELlo");
+        assert!(!tmp_path.exists());
+        assert!(!temp_dir.join("file.tmp.resume").exists());
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+}
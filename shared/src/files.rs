@@ -1,13 +1,18 @@
 use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
 use sha1::{Digest, Sha1};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
 use tokio::fs::File;
-use tokio::io::AsyncReadExt as _;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
 use tokio::{fs, io};
 use walkdir::WalkDir;
 
+use crate::hash_cache::{HashCache, HashCacheStats};
 use crate::progress::{run_tasks_with_progress, ProgressBar};
 
 pub fn get_files_in_dir(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
@@ -45,7 +50,7 @@ pub fn get_files_ignore_paths(
 pub async fn hash_file(path: &Path) -> anyhow::Result<String> {
     let mut file = fs::File::open(path).await?;
     let mut hasher = Sha1::new();
-    let mut buffer = [0; 1024];
+    let mut buffer = vec![0; 64 * 1024];
 
     loop {
         let n = file.read(&mut buffer).await?;
@@ -71,6 +76,38 @@ pub async fn hash_files<M>(
     run_tasks_with_progress(tasks, progress_bar, tasks_count, num_cpus::get()).await
 }
 
+// parallel writers of the same file each get their own temporary file
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn get_unique_tmp_path(path: &Path) -> anyhow::Result<PathBuf> {
+    let mut tmp_name = path
+        .file_name()
+        .ok_or(CopyFilesError::InvalidPath)?
+        .to_os_string();
+    tmp_name.push(format!(
+        ".{}.tmp",
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(tmp_name))
+}
+
+/// Write to a temporary file next to `path` and rename it over `path`,
+/// so readers never see a partially written file.
+pub async fn write_atomic(path: &Path, data: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let tmp_path = get_unique_tmp_path(path)?;
+    fs::write(&tmp_path, data).await?;
+    fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+/// Same as `write_atomic`, for copying a file.
+pub fn copy_atomic(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let tmp_path = get_unique_tmp_path(to)?;
+    std::fs::copy(from, &tmp_path)?;
+    std::fs::rename(&tmp_path, to)?;
+    Ok(())
+}
+
 pub async fn remove_file_or_dir(path: &Path) -> anyhow::Result<()> {
     if path.is_file() {
         fs::remove_file(path).await?;
@@ -84,6 +121,8 @@ pub async fn remove_file_or_dir(path: &Path) -> anyhow::Result<()> {
 pub struct DownloadEntry {
     pub url: String,
     pub path: PathBuf,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -91,6 +130,7 @@ pub struct CheckEntry {
     pub url: String,
     pub remote_sha1: Option<String>,
     pub path: PathBuf,
+    pub size: Option<u64>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -102,6 +142,18 @@ pub enum CheckDownloadError {
 pub async fn get_download_entries<M>(
     check_entries: Vec<CheckEntry>,
     progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
+) -> anyhow::Result<Vec<DownloadEntry>> {
+    let mut hash_cache = HashCache::default();
+    get_download_entries_cached(check_entries, &mut hash_cache, true, progress_bar).await
+}
+
+/// Same as [`get_download_entries`], but takes hashes of unchanged files from `hash_cache`.
+/// `full_verify` ignores the cached hashes and hashes every file again.
+pub async fn get_download_entries_cached<M>(
+    check_entries: Vec<CheckEntry>,
+    hash_cache: &mut HashCache,
+    full_verify: bool,
+    progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
 ) -> anyhow::Result<Vec<DownloadEntry>> {
     let to_hash: Vec<_> = check_entries
         .iter()
@@ -114,7 +166,9 @@ pub async fn get_download_entries<M>(
         })
         .collect();
 
-    let hashes = hash_files(to_hash.clone(), progress_bar.clone()).await?;
+    let hashes = hash_cache
+        .hash_files(to_hash.clone(), full_verify, progress_bar.clone())
+        .await?;
     let hashes = to_hash.into_iter().zip(hashes).collect::<HashMap<_, _>>();
 
     let mut download_entries = HashMap::new();
@@ -138,6 +192,8 @@ pub async fn get_download_entries<M>(
                 DownloadEntry {
                     url: entry.url.clone(),
                     path: entry.path.clone(),
+                    sha1: entry.remote_sha1.clone(),
+                    size: entry.size,
                 },
             );
         }
@@ -146,7 +202,7 @@ pub async fn get_download_entries<M>(
     Ok(download_entries.into_values().collect())
 }
 
-async fn remove_empty_dirs(path: &Path) -> anyhow::Result<()> {
+pub async fn remove_empty_dirs(path: &Path) -> anyhow::Result<()> {
     let root = path;
 
     for entry in WalkDir::new(path)
@@ -181,20 +237,19 @@ pub enum CopyFilesError {
     InvalidPath,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct SyncMappingStats {
     pub total_files: usize,
     pub copied_files: usize,
     pub deleted_files: usize,
+    pub hash_cache: HashCacheStats,
 }
 
-// copy mapped files and directories
-// and delete all other files and directores in the target directory
-// mapping: target -> source
-pub async fn sync_mapping(
+// expand mapped directories into single files, target -> source
+fn get_mapping_files(
     target_dir: &Path,
     mapping: &HashMap<PathBuf, PathBuf>,
-) -> anyhow::Result<SyncMappingStats> {
+) -> anyhow::Result<HashMap<PathBuf, PathBuf>> {
     let mut mappings_files = HashMap::new();
     for (target, source) in mapping {
         if !target.starts_with(target_dir) {
@@ -213,34 +268,98 @@ pub async fn sync_mapping(
             return Err(CopyFilesError::SourceEntryMissing(source.clone()).into());
         }
     }
+    Ok(mappings_files)
+}
 
-    let mut deleted_files: usize = 0;
-    let paths = get_files_in_dir(target_dir)?;
-    for path in paths {
-        if !mappings_files.contains_key(&path) {
-            fs::remove_file(&path).await?;
-            deleted_files += 1;
-        }
-    }
+// copy mapped files and directories
+// and delete all other files and directores in the target directory
+// mapping: target -> source
+pub async fn sync_mapping(
+    target_dir: &Path,
+    mapping: &HashMap<PathBuf, PathBuf>,
+) -> anyhow::Result<SyncMappingStats> {
+    sync_mapping_cached(target_dir, mapping, &mut HashCache::default()).await
+}
 
+// same as sync_mapping, but source and target hashes of unchanged files are taken from hash_cache
+pub async fn sync_mapping_cached(
+    target_dir: &Path,
+    mapping: &HashMap<PathBuf, PathBuf>,
+    hash_cache: &mut HashCache,
+) -> anyhow::Result<SyncMappingStats> {
+    let unmapped_files = get_unmapped_files(target_dir, mapping)?;
+    for path in &unmapped_files {
+        fs::remove_file(path).await?;
+    }
     remove_empty_dirs(target_dir).await?;
 
-    async fn copy_file_if_needed(target: PathBuf, source: PathBuf) -> anyhow::Result<bool> {
+    let stats = copy_mapping_cached(target_dir, mapping, hash_cache).await?;
+    Ok(SyncMappingStats {
+        deleted_files: unmapped_files.len(),
+        ..stats
+    })
+}
+
+// files in the target directory that the mapping doesn't cover
+pub fn get_unmapped_files(
+    target_dir: &Path,
+    mapping: &HashMap<PathBuf, PathBuf>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mappings_files = get_mapping_files(target_dir, mapping)?;
+    Ok(get_files_in_dir(target_dir)?
+        .into_iter()
+        .filter(|path| !mappings_files.contains_key(path))
+        .collect())
+}
+
+// copy mapped files and directories, leaving other files in the target directory alone
+pub async fn copy_mapping_cached(
+    target_dir: &Path,
+    mapping: &HashMap<PathBuf, PathBuf>,
+    hash_cache: &mut HashCache,
+) -> anyhow::Result<SyncMappingStats> {
+    let stats_before = hash_cache.stats();
+    let mappings_files = get_mapping_files(target_dir, mapping)?;
+
+    async fn hash_file_cached(
+        path: &Path,
+        hash_cache: &Mutex<&mut HashCache>,
+    ) -> anyhow::Result<String> {
+        let cached = hash_cache.lock().unwrap().lookup(path);
+        if let Some(sha1) = cached {
+            return Ok(sha1);
+        }
+        let sha1 = hash_file(path).await?;
+        hash_cache.lock().unwrap().insert(path, sha1.clone());
+        Ok(sha1)
+    }
+
+    async fn copy_file_if_needed(
+        target: PathBuf,
+        source: PathBuf,
+        hash_cache: &Mutex<&mut HashCache>,
+    ) -> anyhow::Result<bool> {
         fs::create_dir_all(target.parent().ok_or(CopyFilesError::InvalidPath)?).await?;
         if target.is_dir() {
             fs::remove_dir(&target).await?;
         }
+        let source_hash = hash_file_cached(&source, hash_cache).await?;
         let should_copy =
-            !target.exists() || hash_file(&source).await? != hash_file(&target).await?;
+            !target.exists() || source_hash != hash_file_cached(&target, hash_cache).await?;
         if should_copy {
             // copy and let umask set the permissions instead of fs::copy
             let mut src = File::open(&source).await?;
             let mut dst = File::create(&target).await?;
             io::copy(&mut src, &mut dst).await?;
+            dst.flush().await?;
+            drop(dst);
+            hash_cache.lock().unwrap().insert(&target, source_hash);
         }
         Ok(should_copy)
     }
 
+    let hash_cache = Mutex::new(hash_cache);
+
     const MAX_CONCURRENT_FILE_OPERATIONS: usize = 50;
 
     let total_files = mappings_files.len();
@@ -251,7 +370,11 @@ pub async fn sync_mapping(
 
     for _ in 0..MAX_CONCURRENT_FILE_OPERATIONS.min(mappings_files.len()) {
         if let Some((target, source)) = mapping_iter.next() {
-            tasks.push(copy_file_if_needed(target.clone(), source.clone()));
+            tasks.push(copy_file_if_needed(
+                target.clone(),
+                source.clone(),
+                &hash_cache,
+            ));
         }
     }
 
@@ -261,17 +384,64 @@ pub async fn sync_mapping(
         }
 
         if let Some((target, source)) = mapping_iter.next() {
-            tasks.push(copy_file_if_needed(target.clone(), source.clone()));
+            tasks.push(copy_file_if_needed(
+                target.clone(),
+                source.clone(),
+                &hash_cache,
+            ));
         }
     }
 
+    drop(tasks);
+    let stats_after = hash_cache.into_inner().unwrap().stats();
+
     Ok(SyncMappingStats {
         total_files,
         copied_files,
-        deleted_files,
+        deleted_files: 0,
+        hash_cache: HashCacheStats {
+            hits: stats_after.hits - stats_before.hits,
+            misses: stats_after.misses - stats_before.misses,
+        },
     })
 }
 
+#[derive(Debug, Default, Serialize)]
+pub struct MappingPlan {
+    pub added: Vec<PathBuf>,
+    pub changed: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+}
+
+// what sync_mapping would do, without touching the target directory
+pub async fn plan_mapping(
+    target_dir: &Path,
+    mapping: &HashMap<PathBuf, PathBuf>,
+    hash_cache: &mut HashCache,
+) -> anyhow::Result<MappingPlan> {
+    let mappings_files = get_mapping_files(target_dir, mapping)?;
+
+    let mut plan = MappingPlan::default();
+    for path in get_files_in_dir(target_dir)? {
+        if !mappings_files.contains_key(&path) {
+            plan.deleted.push(path);
+        }
+    }
+
+    for (target, source) in mappings_files {
+        if !target.is_file() {
+            plan.added.push(target);
+        } else if hash_cache.hash_file(&source).await? != hash_cache.hash_file(&target).await? {
+            plan.changed.push(target);
+        }
+    }
+
+    plan.added.sort();
+    plan.changed.sort();
+    plan.deleted.sort();
+    Ok(plan)
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
@@ -327,4 +497,72 @@ mod tests {
         fs::remove_dir_all(&source_dir).await.unwrap();
         fs::remove_dir_all(&target_dir).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_copy_mapping() {
+        let temp_dir = env::temp_dir().join("copy_mapping_test");
+        let source_dir = temp_dir.join("source");
+        let target_dir = temp_dir.join("target");
+        fs::create_dir_all(&source_dir).await.unwrap();
+        fs::create_dir_all(&target_dir).await.unwrap();
+
+        fs::write(source_dir.join("new"), "new").await.unwrap();
+        fs::write(target_dir.join("old"), "old").await.unwrap();
+
+        let mappings = hashmap! {
+            target_dir.join("new") => source_dir.join("new"),
+        };
+        let stats = copy_mapping_cached(&target_dir, &mappings, &mut HashCache::default())
+            .await
+            .unwrap();
+
+        assert_eq!(stats.copied_files, 1);
+        assert_eq!(stats.deleted_files, 0);
+        assert_eq!(fs::read(target_dir.join("new")).await.unwrap(), b"new");
+        assert_eq!(
+            get_unmapped_files(&target_dir, &mappings).unwrap(),
+            vec![target_dir.join("old")]
+        );
+
+        write_atomic(&target_dir.join("old"), "replaced")
+            .await
+            .unwrap();
+        assert_eq!(fs::read(target_dir.join("old")).await.unwrap(), b"replaced");
+        // no temporary file is left behind
+        assert_eq!(std::fs::read_dir(&target_dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plan_mapping() {
+        let temp_dir = env::temp_dir().join("plan_mapping_test");
+        let source_dir = temp_dir.join("source");
+        let target_dir = temp_dir.join("target");
+        fs::create_dir_all(&source_dir).await.unwrap();
+        fs::create_dir_all(&target_dir).await.unwrap();
+
+        fs::write(source_dir.join("new"), "new").await.unwrap();
+        fs::write(source_dir.join("same"), "same").await.unwrap();
+        fs::write(source_dir.join("changed"), "changed")
+            .await
+            .unwrap();
+        fs::write(target_dir.join("same"), "same").await.unwrap();
+        fs::write(target_dir.join("changed"), "old").await.unwrap();
+        fs::write(target_dir.join("extra"), "extra").await.unwrap();
+
+        let mappings = hashmap! {
+            target_dir.clone() => source_dir.clone(),
+        };
+        let plan = plan_mapping(&target_dir, &mappings, &mut HashCache::default())
+            .await
+            .unwrap();
+
+        assert_eq!(plan.added, vec![target_dir.join("new")]);
+        assert_eq!(plan.changed, vec![target_dir.join("changed")]);
+        assert_eq!(plan.deleted, vec![target_dir.join("extra")]);
+        assert_eq!(fs::read(target_dir.join("changed")).await.unwrap(), b"old");
+
+        fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
 }
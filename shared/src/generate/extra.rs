@@ -6,42 +6,52 @@ use std::{
 
 use crate::{
     files,
-    paths::{get_libraries_dir, get_rel_minecraft_dir, get_versions_extra_dir},
+    hash_cache::HashCache,
+    path_filter::PathFilter,
+    paths::{get_libraries_dir, get_rel_object_path, get_versions_extra_dir},
     progress::{self, NoProgressBar, ProgressBar as _},
     utils::{url_from_path, url_from_rel_path},
     version::{
-        extra_version_metadata::{AuthBackend, ExtraVersionMetadata, Include, Object},
-        version_metadata::Library,
+        extra_version_metadata::{
+            AuthBackend, ExtraVersionMetadata, Include, JavaRuntime, Object, OptionalGroup,
+        },
+        version_metadata::{Library, Rule},
     },
 };
-use log::info;
-use serde::Deserialize;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
 
 async fn get_objects(
     copy_from: &Path,
     from: &Path,
     download_server_base: &str,
-    version_name: &str,
     existing_paths: &HashSet<PathBuf>,
+    path_filter: &PathFilter,
+    hash_cache: &mut HashCache,
 ) -> anyhow::Result<Vec<Object>> {
-    let files = files::get_files_ignore_paths(from, existing_paths)?;
+    let mut files = files::get_files_ignore_paths(from, existing_paths)?;
+    files.retain(|file| {
+        file.strip_prefix(copy_from)
+            .is_ok_and(|rel_path| path_filter.matches(&rel_path.to_string_lossy()))
+    });
 
     let rel_paths = files
         .iter()
         .map(|p| p.strip_prefix(copy_from))
         .collect::<Result<Vec<_>, _>>()?;
-    let hashes = files::hash_files(files.clone(), progress::no_progress_bar()).await?;
+    let hashes = hash_cache
+        .hash_files(files.clone(), false, progress::no_progress_bar())
+        .await?;
 
     let mut objects = vec![];
-    for (rel_path, hash) in rel_paths.iter().zip(hashes.iter()) {
-        let url = url_from_rel_path(
-            &get_rel_minecraft_dir(version_name).join(rel_path),
-            download_server_base,
-        )?;
+    for ((rel_path, hash), file) in rel_paths.iter().zip(hashes.iter()).zip(files.iter()) {
+        let size = tokio::fs::metadata(file).await?.len();
+        let url = url_from_rel_path(&get_rel_object_path(hash), download_server_base)?;
         objects.push(Object {
             path: rel_path.to_string_lossy().to_string().replace('\\', "/"),
             sha1: hash.clone(),
             url,
+            size: Some(size),
         });
     }
 
@@ -58,12 +68,15 @@ async fn get_extra_forge_libs(
     extra_forge_libs_paths: &[PathBuf],
     data_dir: &Path,
     download_server_base: &str,
+    hash_cache: &mut HashCache,
 ) -> anyhow::Result<Vec<Library>> {
     let libraries_dir = get_libraries_dir(data_dir);
 
     let progress_bar = Arc::new(NoProgressBar);
     progress_bar.set_message("Hashing extra forge libraries");
-    let hashes = files::hash_files::<&str>(extra_forge_libs_paths.to_vec(), progress_bar).await?;
+    let hashes = hash_cache
+        .hash_files::<&str>(extra_forge_libs_paths.to_vec(), false, progress_bar)
+        .await?;
 
     let libraries = extra_forge_libs_paths
         .iter()
@@ -101,7 +114,9 @@ async fn get_extra_forge_libs(
 
             let name = format!("{group}:{name}:{version}{suffix}");
 
-            Ok(Library::from_download(name, url, hash.clone()))
+            let size = path.metadata()?.len();
+
+            Ok(Library::from_download(name, url, hash.clone(), Some(size)))
         })
         .collect::<anyhow::Result<_>>()?;
 
@@ -109,8 +124,8 @@ async fn get_extra_forge_libs(
 }
 
 pub struct GeneratorResult {
-    // relative include path -> absolute source path
-    pub include_mapping: HashMap<String, PathBuf>,
+    // sha1 -> absolute source path of every object
+    pub object_sources: HashMap<String, PathBuf>,
 
     pub extra_metadata: ExtraVersionMetadata,
 }
@@ -119,6 +134,53 @@ fn yes() -> bool {
     true
 }
 
+#[derive(Deserialize)]
+pub struct ModrinthMod {
+    // project id or slug
+    pub project: Option<String>,
+    // version id or version number, the latest compatible version if not set
+    pub version: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MavenArtifact {
+    // base url of the repository, e.g. https://maven.fabricmc.net
+    pub repository: String,
+    // group:artifact:version[:classifier][@extension], the version can be a selector
+    pub coordinates: String,
+}
+
+// a file that players download from its original url
+#[derive(Serialize, Deserialize)]
+pub struct IncludeDownload {
+    // relative to the path of the rule
+    pub path: String,
+    pub url: String,
+    pub sha1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    // left out of server packs
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub client_only: bool,
+}
+
+// a file that is downloaded by the builder instead of being taken from include_from
+pub struct RemoteFile {
+    // relative to the path of the rule, can contain directories
+    pub filename: String,
+    pub url: String,
+    pub sha1: String,
+    pub sha512: Option<String>,
+    pub size: Option<u64>,
+    // published from download_server_base, otherwise players download it from `url`
+    pub rehost: bool,
+    pub client_only: bool,
+    // where the builder downloaded the file to
+    pub path: PathBuf,
+}
+
 #[derive(Deserialize)]
 pub struct IncludeRule {
     pub path: String,
@@ -131,11 +193,38 @@ pub struct IncludeRule {
 
     #[serde(default)]
     pub recursive: bool,
+
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    pub rules: Option<Vec<Rule>>,
+
+    pub optional_group: Option<OptionalGroup>,
+
+    // left out of server packs, e.g. shaders or client-side mods
+    #[serde(default)]
+    pub client_only: bool,
+
+    #[serde(default)]
+    pub modrinth: Vec<ModrinthMod>,
+
+    #[serde(default)]
+    pub maven: Vec<MavenArtifact>,
+
+    #[serde(default)]
+    pub downloads: Vec<IncludeDownload>,
+
+    // resolved from the mod sources above, placed into `path`
+    #[serde(skip)]
+    pub remote_files: Vec<RemoteFile>,
 }
 
 pub struct IncludeConfig {
     pub include: Vec<IncludeRule>,
-    pub include_from: String,
+    pub include_from: Option<String>,
     pub download_server_base: String,
     pub resources_url_base: Option<String>,
 }
@@ -146,6 +235,8 @@ pub struct ExtraMetadataGenerator {
     extra_forge_libs_paths: Vec<PathBuf>,
     auth_backend: Option<AuthBackend>,
     recommended_xmx: Option<String>,
+    // runtime -> archive to publish
+    java_runtimes: Vec<(JavaRuntime, PathBuf)>,
 }
 
 impl ExtraMetadataGenerator {
@@ -155,6 +246,7 @@ impl ExtraMetadataGenerator {
         extra_forge_libs_paths: Vec<PathBuf>,
         auth_backend: Option<AuthBackend>,
         recommended_xmx: Option<String>,
+        java_runtimes: Vec<(JavaRuntime, PathBuf)>,
     ) -> Self {
         Self {
             version_name,
@@ -162,10 +254,15 @@ impl ExtraMetadataGenerator {
             extra_forge_libs_paths,
             auth_backend,
             recommended_xmx,
+            java_runtimes,
         }
     }
 
-    pub async fn generate(self, work_dir: &Path) -> anyhow::Result<GeneratorResult> {
+    pub async fn generate(
+        self,
+        work_dir: &Path,
+        hash_cache: &mut HashCache,
+    ) -> anyhow::Result<GeneratorResult> {
         info!(
             "Generating extra metadata for instance {}",
             self.version_name
@@ -177,43 +274,90 @@ impl ExtraMetadataGenerator {
             auth_backend: self.auth_backend,
             extra_forge_libs: vec![],
             recommended_xmx: self.recommended_xmx,
+            java_runtimes: vec![],
         };
 
-        let mut include_mapping = HashMap::new();
+        let mut object_sources = HashMap::new();
+        for (runtime, path) in self.java_runtimes {
+            object_sources.insert(runtime.sha1.clone(), path);
+            extra_metadata.java_runtimes.push(runtime);
+        }
 
         if let Some(include_config) = self.include_config {
             let extra_forge_libs = get_extra_forge_libs(
                 &self.extra_forge_libs_paths,
                 work_dir,
                 &include_config.download_server_base,
+                hash_cache,
             )
             .await?;
 
-            let copy_from = PathBuf::from(&include_config.include_from);
+            let copy_from = include_config.include_from.as_ref().map(PathBuf::from);
 
             let mut include = vec![];
             let mut existing_paths = HashSet::new();
             for rule in include_config.include.iter() {
-                let from = copy_from.join(Path::new(&rule.path));
-
-                let objects = get_objects(
-                    &copy_from,
-                    &from,
-                    &include_config.download_server_base,
-                    &self.version_name,
-                    &existing_paths,
-                )
-                .await?;
-                include_mapping.insert(rule.path.clone(), from.clone());
+                if copy_from.is_none() && rule.remote_files.is_empty() {
+                    warn!(
+                        "Ignoring include rule {}, include_from is not set",
+                        rule.path
+                    );
+                    continue;
+                }
+                let path_filter = PathFilter::new(&rule.include, &rule.exclude)?;
+
+                let mut objects = vec![];
+                if let Some(copy_from) = &copy_from {
+                    let from = copy_from.join(Path::new(&rule.path));
+                    objects = get_objects(
+                        copy_from,
+                        &from,
+                        &include_config.download_server_base,
+                        &existing_paths,
+                        &path_filter,
+                        hash_cache,
+                    )
+                    .await?;
+                    for object in &objects {
+                        object_sources.insert(object.sha1.clone(), copy_from.join(&object.path));
+                    }
+                    existing_paths.insert(from);
+                }
+
+                for file in &rule.remote_files {
+                    let path = format!("{}/{}", rule.path.trim_end_matches('/'), file.filename);
+                    if objects.iter().any(|object| object.path == path) {
+                        warn!("Skipping downloaded {path}, it is already in include_from");
+                        continue;
+                    }
+                    let url = if file.rehost {
+                        object_sources.insert(file.sha1.clone(), file.path.clone());
+                        url_from_rel_path(
+                            &get_rel_object_path(&file.sha1),
+                            &include_config.download_server_base,
+                        )?
+                    } else {
+                        file.url.clone()
+                    };
+                    objects.push(Object {
+                        path,
+                        sha1: file.sha1.clone(),
+                        url,
+                        size: file.size,
+                    });
+                }
 
                 include.push(Include {
                     path: rule.path.clone(),
                     overwrite: rule.overwrite,
                     delete_extra: rule.delete_extra,
                     recursive: rule.recursive,
+                    include: rule.include.clone(),
+                    exclude: rule.exclude.clone(),
+                    rules: rule.rules.clone(),
+                    optional_group: rule.optional_group.clone(),
                     objects,
                 });
-                existing_paths.insert(from);
             }
 
             extra_metadata.include = include;
@@ -232,7 +376,7 @@ impl ExtraMetadataGenerator {
         );
 
         Ok(GeneratorResult {
-            include_mapping,
+            object_sources,
             extra_metadata,
         })
     }
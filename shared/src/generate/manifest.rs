@@ -16,6 +16,22 @@ use crate::{
     },
 };
 
+// the file that is published for the metadata, replaced metadata takes precedence
+pub fn get_metadata_source_path(
+    work_dir: &Path,
+    version_id: &str,
+    replaced_metadata: &HashMap<String, PathBuf>,
+) -> PathBuf {
+    replaced_metadata
+        .get(version_id)
+        .cloned()
+        .unwrap_or_else(|| {
+            work_dir
+                .join(get_rel_versions_dir())
+                .join(get_rel_metadata_path(version_id))
+        })
+}
+
 pub async fn get_version_info(
     work_dir: &Path,
     version_metadata: &Vec<VersionMetadata>,
@@ -30,10 +46,7 @@ pub async fn get_version_info(
     let mut metadata_info = vec![];
     for metadata in version_metadata {
         let rel_metadata_path = rel_versions_dir.join(get_rel_metadata_path(&metadata.id));
-        let metadata_path = replaced_metadata
-            .get(&metadata.id)
-            .cloned()
-            .unwrap_or_else(|| work_dir.join(&rel_metadata_path));
+        let metadata_path = get_metadata_source_path(work_dir, &metadata.id, replaced_metadata);
         metadata_info.push(MetadataInfo {
             id: metadata.id.clone(),
             url: url_from_rel_path(&rel_metadata_path, download_server_base)?,
@@ -66,5 +79,8 @@ pub async fn get_version_info(
         inherits_from: metadata_info,
         extra_metadata_url,
         extra_metadata_sha1,
+        channel: None,
+        built_at: None,
+        history: vec![],
     })
 }
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::files;
+use crate::progress::ProgressBar;
+
+// a cached hash is only trusted while the file still has the same stamp
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct FileStamp {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inode: Option<u64>,
+}
+
+impl FileStamp {
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt as _;
+            Some(metadata.ino())
+        };
+        #[cfg(not(unix))]
+        let inode = None;
+
+        Some(Self {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+            inode,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CachedHash {
+    sha1: String,
+    #[serde(flatten)]
+    stamp: FileStamp,
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize)]
+pub struct HashCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl HashCacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64 * 100.0
+        }
+    }
+}
+
+impl std::fmt::Display for HashCacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1}% ({}/{})",
+            self.hit_rate(),
+            self.hits,
+            self.hits + self.misses
+        )
+    }
+}
+
+/// Persistent index of file hashes, so unchanged files don't have to be hashed again.
+#[derive(Serialize, Deserialize, Default)]
+pub struct HashCache {
+    hashes: HashMap<PathBuf, CachedHash>,
+
+    #[serde(skip)]
+    stats: HashCacheStats,
+}
+
+impl HashCache {
+    pub async fn load(path: &Path) -> Self {
+        let data = match tokio::fs::read(path).await {
+            Ok(data) => data,
+            Err(_) => return Self::default(),
+        };
+        match serde_json::from_slice(&data) {
+            Ok(cache) => cache,
+            Err(e) => {
+                warn!("Failed to read hash cache, starting from scratch: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(self)?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&str> {
+        let cached = self.hashes.get(path)?;
+        if FileStamp::read(path).as_ref() == Some(&cached.stamp) {
+            Some(&cached.sha1)
+        } else {
+            None
+        }
+    }
+
+    // same as get, but counted in the stats
+    pub fn lookup(&mut self, path: &Path) -> Option<String> {
+        let sha1 = self.get(path).map(|sha1| sha1.to_string());
+        if sha1.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+        sha1
+    }
+
+    pub fn stats(&self) -> HashCacheStats {
+        self.stats
+    }
+
+    /// Copy of the cache for a job running in parallel with others, see `merge`.
+    pub fn fork(&self) -> Self {
+        Self {
+            hashes: self.hashes.clone(),
+            stats: HashCacheStats::default(),
+        }
+    }
+
+    /// Take over the hashes and stats of a forked cache.
+    pub fn merge(&mut self, other: HashCache) {
+        self.hashes.extend(other.hashes);
+        self.stats.hits += other.stats.hits;
+        self.stats.misses += other.stats.misses;
+    }
+
+    /// Forget the files that don't exist anymore.
+    pub fn prune(&mut self) {
+        self.hashes.retain(|path, _| path.is_file());
+    }
+
+    pub async fn hash_file(&mut self, path: &Path) -> anyhow::Result<String> {
+        if let Some(sha1) = self.lookup(path) {
+            return Ok(sha1);
+        }
+        let sha1 = files::hash_file(path).await?;
+        self.insert(path, sha1.clone());
+        Ok(sha1)
+    }
+
+    pub fn insert(&mut self, path: &Path, sha1: String) {
+        match FileStamp::read(path) {
+            Some(stamp) => {
+                self.hashes
+                    .insert(path.to_path_buf(), CachedHash { sha1, stamp });
+            }
+            None => {
+                self.hashes.remove(path);
+            }
+        }
+    }
+
+    /// Hash `files`, reusing cached hashes of unchanged files.
+    /// With `full_verify` every file is hashed again and the cache is refreshed.
+    pub async fn hash_files<M>(
+        &mut self,
+        files: Vec<PathBuf>,
+        full_verify: bool,
+        progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut hashes = files
+            .iter()
+            .map(|path| {
+                if full_verify {
+                    self.stats.misses += 1;
+                    None
+                } else {
+                    self.lookup(path)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let to_hash = files
+            .iter()
+            .zip(hashes.iter())
+            .filter(|(_, hash)| hash.is_none())
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        info!(
+            "Reusing {} cached hashes, hashing {} files",
+            files.len() - to_hash.len(),
+            to_hash.len()
+        );
+
+        let new_hashes = files::hash_files(to_hash.clone(), progress_bar).await?;
+        for (path, sha1) in to_hash.iter().zip(new_hashes.iter()) {
+            self.insert(path, sha1.clone());
+        }
+
+        let mut new_hashes = new_hashes.into_iter();
+        for hash in hashes.iter_mut() {
+            if hash.is_none() {
+                *hash = new_hashes.next();
+            }
+        }
+
+        Ok(hashes.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::progress::no_progress_bar;
+
+    #[tokio::test]
+    async fn test_hash_cache() {
+        let temp_dir = env::temp_dir().join("hash_cache_test");
+        let file = temp_dir.join("file");
+        let cache_path = temp_dir.join("hash_cache.json");
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        tokio::fs::write(&file, "hello").await.unwrap();
+
+        let mut cache = HashCache::default();
+        // a wrong cached hash shows whether the cache was used
+        cache.insert(&file, "cached".to_string());
+        cache.save(&cache_path).await.unwrap();
+
+        let mut cache = HashCache::load(&cache_path).await;
+        let hashes = cache
+            .hash_files(vec![file.clone()], false, no_progress_bar())
+            .await
+            .unwrap();
+        assert_eq!(hashes, vec!["cached".to_string()]);
+
+        let hashes = cache
+            .hash_files(vec![file.clone()], true, no_progress_bar())
+            .await
+            .unwrap();
+        assert_eq!(hashes, vec![files::hash_file(&file).await.unwrap()]);
+
+        cache.insert(&file, "cached".to_string());
+        tokio::fs::write(&file, "changed").await.unwrap();
+        assert_eq!(cache.get(&file), None);
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fork_merge() {
+        let temp_dir = env::temp_dir().join("hash_cache_fork_test");
+        let file = temp_dir.join("file");
+        let other_file = temp_dir.join("other_file");
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        tokio::fs::write(&file, "hello").await.unwrap();
+        tokio::fs::write(&other_file, "world").await.unwrap();
+
+        let mut cache = HashCache::default();
+        cache.insert(&file, "cached".to_string());
+
+        let mut fork = cache.fork();
+        assert_eq!(fork.lookup(&file).as_deref(), Some("cached"));
+        fork.hash_file(&other_file).await.unwrap();
+        assert_eq!(cache.get(&other_file), None);
+
+        cache.merge(fork);
+        assert_eq!(
+            cache.get(&other_file),
+            Some(files::hash_file(&other_file).await.unwrap().as_str())
+        );
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+}
@@ -1,13 +1,14 @@
 use flate2::read::GzDecoder;
 use futures::StreamExt;
+use log::{info, warn};
 use regex::Regex;
 use reqwest::{Client, Url};
 use serde::Deserialize;
 use std::fs;
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 use tar::Archive;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 
 use serde_json::Value;
@@ -16,7 +17,11 @@ use winreg::enums::*;
 #[cfg(target_os = "windows")]
 use winreg::RegKey;
 
+use crate::files::hash_file;
+use crate::partial_download::{self, PartialDownload};
 use crate::progress::ProgressBar;
+use crate::utils::KeyedLocks;
+use crate::version::extra_version_metadata::JavaRuntime;
 
 #[derive(Debug, Deserialize)]
 pub struct JavaInstallation {
@@ -242,21 +247,63 @@ enum JavaDownloadError {
     NoDownloadURL,
     #[error("No file name in URL")]
     NoFileNameInURL,
-    #[error("No file extension in URL")]
-    NoFileExtensionInURL,
+    #[error("Invalid Java archive name: {0}")]
+    InvalidArchiveName(String),
+    #[error("sha1 of {0} doesn't match, expected {1}, got {2}")]
+    Sha1Mismatch(String, String, String),
 }
 
-fn get_java_download_params(required_version: &str, archive_type: &str) -> anyhow::Result<String> {
+const ARCHIVE_TYPES: [&str; 2] = ["tar.gz", "zip"];
+
+/// A Java archive to download, it unpacks into a directory named like the archive.
+pub struct JavaArchive {
+    pub url: String,
+    pub filename: String,
+    pub sha1: Option<String>,
+}
+
+impl From<&JavaRuntime> for JavaArchive {
+    fn from(runtime: &JavaRuntime) -> Self {
+        Self {
+            url: runtime.url.clone(),
+            filename: runtime.filename.clone(),
+            sha1: Some(runtime.sha1.clone()),
+        }
+    }
+}
+
+// os and arch of this machine, named like in library rules
+fn get_current_platform() -> anyhow::Result<(&'static str, &'static str)> {
+    let os_name = match std::env::consts::OS {
+        "windows" => "windows",
+        "linux" => "linux",
+        "macos" => "osx",
+        _ => return Err(JavaDownloadError::UnsupportedOS.into()),
+    };
     let arch = match std::env::consts::ARCH {
-        "x86_64" | "amd64" => "x64",
-        "aarch64" => "aarch64",
+        "x86_64" | "amd64" => "x86_64",
+        "aarch64" => "arm64",
         _ => return Err(JavaDownloadError::UnsupportedArchitecture.into()),
     };
+    Ok((os_name, arch))
+}
 
-    let os = match std::env::consts::OS {
+fn get_java_download_params(
+    required_version: &str,
+    os_name: &str,
+    arch: &str,
+    archive_type: &str,
+) -> anyhow::Result<String> {
+    let arch = match arch {
+        "x86_64" => "x64",
+        "arm64" => "aarch64",
+        _ => return Err(JavaDownloadError::UnsupportedArchitecture.into()),
+    };
+
+    let os = match os_name {
         "windows" => "windows",
         "linux" => "linux-glibc",
-        "macos" => "macos",
+        "osx" => "macos",
         _ => return Err(JavaDownloadError::UnsupportedOS.into()),
     };
 
@@ -267,24 +314,16 @@ fn get_java_download_params(required_version: &str, archive_type: &str) -> anyho
     Ok(params)
 }
 
-pub fn get_temp_dir() -> PathBuf {
-    let temp_dir = std::env::temp_dir();
-    let temp_dir = temp_dir.join("temp_java_download");
-    if !temp_dir.exists() {
-        fs::create_dir_all(&temp_dir).unwrap();
-    }
-    temp_dir
-}
-
-pub async fn download_java<M>(
+/// The latest Azul Zulu JRE for the platform, `os_name` and `arch` are named like in library rules.
+pub async fn find_azul_archive(
     required_version: &str,
-    java_dir: &Path,
-    progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
-) -> anyhow::Result<JavaInstallation> {
+    os_name: &str,
+    arch: &str,
+) -> anyhow::Result<Option<JavaArchive>> {
     let client = Client::new();
 
-    for archive_type in ["tar.gz", "zip"] {
-        let query_str = get_java_download_params(required_version, archive_type)?;
+    for archive_type in ARCHIVE_TYPES {
+        let query_str = get_java_download_params(required_version, os_name, arch, archive_type)?;
 
         let versions_url = format!("https://api.azul.com/metadata/v1/zulu/packages/?{query_str}");
 
@@ -303,56 +342,160 @@ pub async fn download_java<M>(
         let version_url = versions[0]["download_url"]
             .as_str()
             .ok_or(JavaDownloadError::NoDownloadURL)?;
-        let response = client.get(version_url).send().await?;
+        let url = Url::parse(version_url)?;
+        let filename = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .ok_or(JavaDownloadError::NoFileNameInURL)?;
+        return Ok(Some(JavaArchive {
+            url: version_url.to_string(),
+            filename: filename.to_string(),
+            sha1: None,
+        }));
+    }
 
-        let java_download_path = get_temp_dir().join(format!("java_download.{archive_type}"));
-        let mut file = fs::File::create(&java_download_path)?;
+    Ok(None)
+}
 
-        let total_size = response.content_length().unwrap_or(0);
-        progress_bar.set_length(total_size);
+// the name comes from the metadata of the instance and is joined to the launcher dirs,
+// so it has to be a plain file name
+fn get_archive_type(filename: &str) -> Option<&'static str> {
+    let mut components = Path::new(filename).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return None;
+    }
+    ARCHIVE_TYPES.into_iter().find(|archive_type| {
+        filename
+            .strip_suffix(&format!(".{archive_type}"))
+            .is_some_and(|name| !name.is_empty())
+    })
+}
 
-        let mut stream = response.bytes_stream();
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            file.write_all(&chunk)?;
-            progress_bar.inc(chunk.len() as u64);
-        }
-        progress_bar.finish();
+pub fn get_temp_dir() -> PathBuf {
+    let temp_dir = std::env::temp_dir();
+    let temp_dir = temp_dir.join("temp_java_download");
+    if !temp_dir.exists() {
+        fs::create_dir_all(&temp_dir).unwrap();
+    }
+    temp_dir
+}
 
-        let target_dir = java_dir.join(required_version);
-        if target_dir.exists() {
-            fs::remove_dir_all(&target_dir)?;
-        }
+/// Download `url` to `path`, resuming an interrupted download.
+pub async fn download_archive<M>(
+    url: &str,
+    path: &Path,
+    progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
+) -> anyhow::Result<()> {
+    let client = Client::new();
+    let tmp_path = partial_download::get_tmp_path(path);
+    let PartialDownload {
+        response,
+        mut file,
+        offset,
+    } = partial_download::start_download(&client, url, &tmp_path).await?;
+
+    let total_size = response.content_length().unwrap_or(0) + offset;
+    progress_bar.set_length(total_size);
+    progress_bar.inc(offset);
+
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        progress_bar.inc(chunk.len() as u64);
+    }
+    file.flush().await?;
+    drop(file);
+    progress_bar.finish();
+
+    partial_download::finish_download(&tmp_path).await;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
 
-        let archive = fs::File::open(&java_download_path)?;
-        if archive_type == "tar.gz" {
-            let tar = GzDecoder::new(archive);
-            let mut archive = Archive::new(tar);
-            archive.unpack(java_dir)?;
-        } else {
-            let mut archive = zip::ZipArchive::new(archive)?;
-            archive.extract(java_dir)?;
+async fn install_java_archive<M>(
+    archive: &JavaArchive,
+    required_version: &str,
+    java_dir: &Path,
+    progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
+) -> anyhow::Result<JavaInstallation> {
+    let archive_type = get_archive_type(&archive.filename)
+        .ok_or_else(|| JavaDownloadError::InvalidArchiveName(archive.filename.clone()))?;
+
+    // named after the archive, so that a resumed download continues the same file
+    let java_download_path = get_temp_dir().join(&archive.filename);
+    download_archive(&archive.url, &java_download_path, progress_bar).await?;
+    if let Some(sha1) = &archive.sha1 {
+        let actual = hash_file(&java_download_path).await?;
+        if actual != *sha1 {
+            fs::remove_file(&java_download_path)?;
+            return Err(JavaDownloadError::Sha1Mismatch(
+                archive.filename.clone(),
+                sha1.clone(),
+                actual,
+            )
+            .into());
         }
+    }
 
-        let url = Url::parse(version_url)?;
-        let filename = url
-            .path_segments()
-            .and_then(|mut segments| segments.next_back())
-            .ok_or(JavaDownloadError::NoFileNameInURL)?
-            .strip_suffix(&format!(".{archive_type}"))
-            .ok_or(JavaDownloadError::NoFileExtensionInURL)?;
-        fs::rename(java_dir.join(filename), &target_dir)?;
+    let target_dir = java_dir.join(required_version);
+    if target_dir.exists() {
+        fs::remove_dir_all(&target_dir)?;
+    }
 
-        let java_path = target_dir.join("bin").join(JAVA_BINARY_NAME);
-        if !check_java(required_version, &java_path).await {
-            return Err(JavaDownloadError::InvalidDownloadedJava.into());
-        }
-        if let Some(installation) = get_installation(&java_path).await {
-            return Ok(installation);
+    let archive_file = fs::File::open(&java_download_path)?;
+    if archive_type == "tar.gz" {
+        let tar = GzDecoder::new(archive_file);
+        let mut archive = Archive::new(tar);
+        archive.unpack(java_dir)?;
+    } else {
+        let mut archive = zip::ZipArchive::new(archive_file)?;
+        archive.extract(java_dir)?;
+    }
+    fs::remove_file(&java_download_path)?;
+
+    let dir_name = archive
+        .filename
+        .strip_suffix(&format!(".{archive_type}"))
+        .unwrap();
+    fs::rename(java_dir.join(dir_name), &target_dir)?;
+
+    let java_path = target_dir.join("bin").join(JAVA_BINARY_NAME);
+    if !check_java(required_version, &java_path).await {
+        return Err(JavaDownloadError::InvalidDownloadedJava.into());
+    }
+    get_installation(&java_path)
+        .await
+        .ok_or_else(|| JavaDownloadError::InvalidDownloadedJava.into())
+}
+
+/// Download java from `hosted`, the download server of the instance, and from Azul
+/// if the instance doesn't host java for this platform or the download fails.
+pub async fn download_java<M>(
+    required_version: &str,
+    java_dir: &Path,
+    hosted: Option<JavaArchive>,
+    progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
+) -> anyhow::Result<JavaInstallation> {
+    if let Some(archive) = hosted {
+        match install_java_archive(&archive, required_version, java_dir, progress_bar.clone()).await
+        {
+            Ok(installation) => return Ok(installation),
+            Err(e) => warn!(
+                "Error downloading java from {}, falling back to Azul:\n{e:?}",
+                archive.url
+            ),
         }
     }
 
-    Err(JavaDownloadError::NoJavaVersionsAvailable.into())
+    let (os_name, arch) = get_current_platform()?;
+    let archive = find_azul_archive(required_version, os_name, arch)
+        .await?
+        .ok_or(JavaDownloadError::NoJavaVersionsAvailable)?;
+    install_java_archive(&archive, required_version, java_dir, progress_bar).await
 }
 
 pub async fn get_java(required_version: &str, java_dir: &Path) -> Option<JavaInstallation> {
@@ -376,3 +519,44 @@ pub async fn get_java(required_version: &str, java_dir: &Path) -> Option<JavaIns
 
     None
 }
+
+lazy_static::lazy_static! {
+    // parallel installers must not download the same java at once, by java version
+    static ref JAVA_DOWNLOAD_LOCKS: KeyedLocks = KeyedLocks::default();
+}
+
+pub async fn get_or_download_java<M>(
+    required_version: &str,
+    java_dir: &Path,
+    progress_bar: Arc<dyn ProgressBar<M> + Send + Sync>,
+) -> anyhow::Result<JavaInstallation> {
+    let _guard = JAVA_DOWNLOAD_LOCKS.lock(required_version).await;
+    if let Some(installation) = get_java(required_version, java_dir).await {
+        return Ok(installation);
+    }
+    info!("Java installation not found, downloading");
+    download_java(required_version, java_dir, None, progress_bar).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_archive_type() {
+        assert_eq!(
+            get_archive_type("zulu21.44.17-ca-jre21.0.8-linux_x64.tar.gz"),
+            Some("tar.gz")
+        );
+        assert_eq!(
+            get_archive_type("zulu21.44.17-ca-jre21.0.8-win_x64.zip"),
+            Some("zip")
+        );
+        assert_eq!(get_archive_type("zulu21-linux_x64.tar.xz"), None);
+        assert_eq!(get_archive_type(".zip"), None);
+        assert_eq!(get_archive_type("../zulu21-win_x64.zip"), None);
+        assert_eq!(get_archive_type("java/zulu21-win_x64.zip"), None);
+        assert_eq!(get_archive_type("/tmp/zulu21-win_x64.zip"), None);
+        assert_eq!(get_archive_type(".."), None);
+    }
+}
@@ -1,10 +1,16 @@
 pub mod adaptive_download;
 pub mod files;
 pub mod generate;
+pub mod hash_cache;
 pub mod java;
 pub mod loader_generator;
 pub mod logs;
+pub mod maven;
+pub mod mrpack;
+pub mod partial_download;
+pub mod path_filter;
 pub mod paths;
 pub mod progress;
+pub mod signature;
 pub mod utils;
 pub mod version;
@@ -54,6 +54,71 @@ impl FabricVersionsMeta {
     }
 }
 
+const FABRIC_INSTALLER_META_URL: &str = "https://meta.fabricmc.net/v2/versions/installer";
+
+#[derive(Deserialize)]
+struct FabricInstallerMeta {
+    version: String,
+    stable: bool,
+}
+
+pub async fn get_fabric_version(
+    minecraft_version: &str,
+    loader_version: &Option<String>,
+) -> anyhow::Result<String> {
+    match loader_version {
+        Some(loader_version) => Ok(loader_version.clone()),
+        None => {
+            let meta = FabricVersionsMeta::fetch(minecraft_version).await?;
+            let version =
+                meta.get_latest_version()
+                    .ok_or(FabricGeneratorError::NoVersionsFound(
+                        minecraft_version.to_string(),
+                    ))?;
+            info!("Loader version not specified, using latest version: {version}");
+            Ok(version.to_string())
+        }
+    }
+}
+
+// the launcher jar downloads the loader libraries on the first start
+pub async fn download_fabric_server_launcher(
+    minecraft_version: &str,
+    loader_version: &str,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let client = Client::new();
+    let installers: Vec<FabricInstallerMeta> = client
+        .get(FABRIC_INSTALLER_META_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let installer = installers
+        .iter()
+        .find(|installer| installer.stable)
+        .or(installers.first())
+        .ok_or(FabricGeneratorError::NoInstallersFound)?;
+
+    let url = format!(
+        "{FABRIC_META_BASE_URL}{minecraft_version}/{loader_version}/{}/server/jar",
+        installer.version
+    );
+    let response = client
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, &response).await?;
+    Ok(())
+}
+
 async fn download_fabric_metadata(
     minecraft_version: &str,
     loader_version: &str,
@@ -91,6 +156,8 @@ impl FabricGenerator {
 pub enum FabricGeneratorError {
     #[error("No Fabric versions found for game version {0}")]
     NoVersionsFound(String),
+    #[error("No Fabric installer versions found")]
+    NoInstallersFound,
 }
 
 #[async_trait]
@@ -110,19 +177,7 @@ impl VersionGenerator for FabricGenerator {
         )
         .await?;
 
-        let fabric_version = match &self.loader_version {
-            Some(loader_version) => loader_version.clone(),
-            None => {
-                let meta = FabricVersionsMeta::fetch(&minecraft_version).await?;
-                let version =
-                    meta.get_latest_version()
-                        .ok_or(FabricGeneratorError::NoVersionsFound(
-                            minecraft_version.to_string(),
-                        ))?;
-                info!("Loader version not specified, using latest version: {version}");
-                version.to_string()
-            }
-        };
+        let fabric_version = get_fabric_version(&minecraft_version, &self.loader_version).await?;
 
         info!("Downloading Fabric version metadata");
         let fabric_metadata =
@@ -8,9 +8,11 @@ use std::{
 
 use crate::{
     files,
-    java::{download_java, get_java},
+    java::get_or_download_java,
+    maven::Versioning,
     paths::{get_java_dir, get_libraries_dir, get_metadata_path, get_versions_dir},
     progress::ProgressBar,
+    utils::KeyedLocks,
     version::{version_manifest::VersionInfo, version_metadata::VersionMetadata},
 };
 use async_trait::async_trait;
@@ -19,7 +21,12 @@ use reqwest::Client;
 use serde::Deserialize;
 use tokio::io::AsyncWriteExt as _;
 
-use super::generator::{GeneratorResult, VersionGenerator};
+use super::generator::{GeneratorResult, VersionGenerator, SERVER_LOCK_FILE};
+
+lazy_static::lazy_static! {
+    // instances of the same loader version share the installation, by installer directory
+    static ref FORGE_INSTALL_LOCKS: KeyedLocks = KeyedLocks::default();
+}
 
 const FORGE_MAVEN_METADATA_URL: &str =
     "https://files.minecraftforge.net/net/minecraftforge/forge/maven-metadata.json";
@@ -72,16 +79,6 @@ impl ForgeMavenMetadata {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct Versions {
-    version: Vec<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Versioning {
-    versions: Versions,
-}
-
 #[derive(Debug, Deserialize)]
 pub struct NeoforgeMavenMetadata {
     versioning: Versioning,
@@ -120,8 +117,17 @@ impl NeoforgeMavenMetadata {
     }
 
     pub fn get_latest_matching_version(&self, minecraft_version: &str) -> Option<String> {
+        self.get_latest_version_with_prefix(minecraft_version, "")
+    }
+
+    pub fn get_latest_version_with_prefix(
+        &self,
+        minecraft_version: &str,
+        prefix: &str,
+    ) -> Option<String> {
         self.get_matching_versions(minecraft_version)
             .into_iter()
+            .filter(|version| version.starts_with(prefix))
             .max_by(|a, b| {
                 let a_parts: Vec<u32> = a
                     .split(|c: char| !c.is_ascii_digit())
@@ -374,17 +380,25 @@ fn to_abs_path_str(path: &Path) -> anyhow::Result<String> {
     }
 }
 
-async fn run_forge_command(
+fn get_installer_command(
     java_path: &Path,
     forge_installer_path: &Path,
     forge_work_dir: &Path,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<tokio::process::Command> {
     let mut cmd = tokio::process::Command::new(&to_abs_path_str(java_path)?);
     cmd.current_dir(&to_abs_path_str(forge_work_dir)?)
         .arg("-jar")
-        .arg(&to_abs_path_str(forge_installer_path)?)
-        .arg("--installClient")
-        .arg(".");
+        .arg(&to_abs_path_str(forge_installer_path)?);
+    Ok(cmd)
+}
+
+async fn run_forge_command(
+    java_path: &Path,
+    forge_installer_path: &Path,
+    forge_work_dir: &Path,
+) -> anyhow::Result<()> {
+    let mut cmd = get_installer_command(java_path, forge_installer_path, forge_work_dir)?;
+    cmd.arg("--installClient").arg(".");
     info!("Running forge installer: {cmd:?}");
 
     let output = cmd.output().await?;
@@ -392,11 +406,8 @@ async fn run_forge_command(
         let stderr_str = String::from_utf8_lossy(&output.stderr);
         if stderr_str.contains("'installClient' is not a recognized option") {
             info!("Retrying without '--installClient' argument.");
-            let mut retry_cmd = tokio::process::Command::new(&to_abs_path_str(java_path)?);
-            retry_cmd
-                .current_dir(&to_abs_path_str(forge_work_dir)?)
-                .arg("-jar")
-                .arg(&to_abs_path_str(forge_installer_path)?);
+            let mut retry_cmd =
+                get_installer_command(java_path, forge_installer_path, forge_work_dir)?;
             let retry_output = retry_cmd.output().await?;
             if !retry_output.status.success() {
                 return Err(anyhow::anyhow!(
@@ -436,14 +447,7 @@ pub async fn install_forge<M>(
             });
 
         info!("Getting java {}", &java_version);
-        let java_installation;
-        if let Some(existing_java_installation) = get_java(&java_version, java_dir).await {
-            java_installation = existing_java_installation;
-        } else {
-            info!("Java installation not found, downloading");
-
-            java_installation = download_java(&java_version, java_dir, progress_bar).await?;
-        }
+        let java_installation = get_or_download_java(&java_version, java_dir, progress_bar).await?;
 
         info!("Downloading forge installer");
         let full_version = match loader {
@@ -484,6 +488,60 @@ pub async fn install_forge<M>(
     Ok(id)
 }
 
+/// Run the installer with `--installServer` in `server_dir`, leaving a ready to start server there.
+pub async fn install_forge_server<M>(
+    server_dir: &Path,
+    java_dir: &Path,
+    forge_version: &str,
+    vanilla_metadata: &VersionMetadata,
+    loader: &Loader,
+    progress_bar: Arc<dyn ProgressBar<M>>,
+) -> anyhow::Result<()> {
+    let lock_file = server_dir.join(SERVER_LOCK_FILE);
+    if lock_file.exists() {
+        info!("{loader} {forge_version} server already present, skipping installation");
+        return Ok(());
+    }
+    std::fs::create_dir_all(server_dir)?;
+
+    let java_version = get_vanilla_java_version(vanilla_metadata)
+        .await?
+        .unwrap_or_else(|| {
+            warn!("Java version not found, using default");
+            "8".to_string()
+        });
+    info!("Getting java {}", &java_version);
+    let java_installation = get_or_download_java(&java_version, java_dir, progress_bar).await?;
+
+    info!("Downloading {loader} installer");
+    let full_version = match loader {
+        Loader::Forge => get_full_version(&vanilla_metadata.id, forge_version),
+        Loader::Neoforge => forge_version.to_string(),
+    };
+    let forge_installer_path = download_forge_installer(&full_version, server_dir, loader).await?;
+
+    let mut cmd =
+        get_installer_command(&java_installation.path, &forge_installer_path, server_dir)?;
+    cmd.arg("--installServer").arg(".");
+    info!("Running {loader} server installer: {cmd:?}");
+    let output = cmd.output().await?;
+    if !output.status.success() {
+        error!("Command failed: {output:?}");
+        return Err(anyhow::anyhow!(
+            String::from_utf8_lossy(&output.stderr).to_string()
+        ));
+    }
+
+    // the installer leaves itself and its log next to the server
+    let mut installer_log = forge_installer_path.clone().into_os_string();
+    installer_log.push(".log");
+    let _ = std::fs::remove_file(installer_log);
+    std::fs::remove_file(&forge_installer_path)?;
+
+    std::fs::File::create(lock_file)?;
+    Ok(())
+}
+
 #[async_trait]
 impl VersionGenerator for ForgeGenerator {
     async fn generate(&self, work_dir: &Path) -> anyhow::Result<GeneratorResult> {
@@ -509,6 +567,9 @@ impl VersionGenerator for ForgeGenerator {
         let installer_work_dir = work_dir
             .join(format!(".{:?}", self.loader))
             .join(get_full_version(&minecraft_version, &forge_version));
+        let _guard = FORGE_INSTALL_LOCKS
+            .lock(&installer_work_dir.to_string_lossy())
+            .await;
         let id = install_forge(
             &installer_work_dir,
             &get_java_dir(work_dir),
@@ -562,7 +623,8 @@ impl VersionGenerator for ForgeGenerator {
             .map(|lib_path| {
                 let lib_dest = libraries_dir.join(&lib_path);
                 std::fs::create_dir_all(lib_dest.parent().unwrap())?;
-                std::fs::copy(forge_installer_libraries_dir.join(&lib_path), &lib_dest)?;
+                // other loader versions can copy the same library at the same time
+                files::copy_atomic(&forge_installer_libraries_dir.join(&lib_path), &lib_dest)?;
                 Ok(lib_dest)
             })
             .collect::<anyhow::Result<Vec<_>>>()?;
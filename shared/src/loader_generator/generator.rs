@@ -10,6 +10,9 @@ pub struct GeneratorResult {
     pub extra_libs_paths: Vec<PathBuf>,
 }
 
+// marks a finished server installation of a loader
+pub const SERVER_LOCK_FILE: &str = "server.lock";
+
 #[async_trait]
 pub trait VersionGenerator {
     async fn generate(&self, work_dir: &Path) -> anyhow::Result<GeneratorResult>;
@@ -1,4 +1,5 @@
 pub mod fabric;
 pub mod forge;
 pub mod generator;
+pub mod quilt;
 pub mod vanilla;
@@ -0,0 +1,227 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    java::get_or_download_java,
+    paths::get_versions_dir,
+    progress::ProgressBar,
+    version::{version_manifest::VersionInfo, version_metadata::VersionMetadata},
+};
+use async_trait::async_trait;
+use log::{error, info};
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::generator::{GeneratorResult, VersionGenerator, SERVER_LOCK_FILE};
+
+const QUILT_META_BASE_URL: &str = "https://meta.quiltmc.org/v3/versions/loader/";
+
+#[derive(Deserialize)]
+struct QuiltVersionLoader {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct QuiltVersionMeta {
+    loader: QuiltVersionLoader,
+}
+
+pub struct QuiltVersionsMeta {
+    versions: Vec<QuiltVersionMeta>,
+}
+
+impl QuiltVersionsMeta {
+    pub async fn fetch(game_version: &str) -> anyhow::Result<Self> {
+        let quilt_manifest_url = format!("{QUILT_META_BASE_URL}{game_version}");
+        let client = Client::new();
+        let response = client
+            .get(&quilt_manifest_url)
+            .send()
+            .await?
+            .error_for_status()?;
+        let quilt_versions: Vec<QuiltVersionMeta> = response.json().await?;
+        Ok(Self {
+            versions: quilt_versions,
+        })
+    }
+
+    pub fn get_versions(&self) -> Vec<&str> {
+        self.versions
+            .iter()
+            .map(|version| version.loader.version.as_str())
+            .collect()
+    }
+
+    // quilt meta lists betas first, prefer the newest stable release if there is one
+    pub fn get_latest_version(&self) -> Option<&str> {
+        let versions = self.get_versions();
+        versions
+            .iter()
+            .find(|version| !version.contains('-'))
+            .or(versions.first())
+            .copied()
+    }
+}
+
+const QUILT_INSTALLER_META_URL: &str = "https://meta.quiltmc.org/v3/versions/installer";
+
+#[derive(Deserialize)]
+struct QuiltInstallerMeta {
+    url: String,
+}
+
+pub async fn get_quilt_version(
+    minecraft_version: &str,
+    loader_version: &Option<String>,
+) -> anyhow::Result<String> {
+    match loader_version {
+        Some(loader_version) => Ok(loader_version.clone()),
+        None => {
+            let meta = QuiltVersionsMeta::fetch(minecraft_version).await?;
+            let version = meta
+                .get_latest_version()
+                .ok_or(QuiltGeneratorError::NoVersionsFound(
+                    minecraft_version.to_string(),
+                ))?;
+            info!("Loader version not specified, using latest version: {version}");
+            Ok(version.to_string())
+        }
+    }
+}
+
+/// Install the Quilt server launcher and its libraries into `server_dir`.
+pub async fn install_quilt_server<M>(
+    server_dir: &Path,
+    java_dir: &Path,
+    minecraft_version: &str,
+    loader_version: &str,
+    java_version: &str,
+    progress_bar: Arc<dyn ProgressBar<M>>,
+) -> anyhow::Result<()> {
+    let lock_file = server_dir.join(SERVER_LOCK_FILE);
+    if lock_file.exists() {
+        info!("Quilt {loader_version} server already present, skipping installation");
+        return Ok(());
+    }
+    tokio::fs::create_dir_all(server_dir).await?;
+
+    let client = Client::new();
+    let installers: Vec<QuiltInstallerMeta> = client
+        .get(QUILT_INSTALLER_META_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let installer = installers
+        .first()
+        .ok_or(QuiltGeneratorError::NoInstallersFound)?;
+
+    info!("Downloading Quilt installer");
+    let installer_path = server_dir.join("quilt-installer.jar");
+    let response = client
+        .get(&installer.url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    tokio::fs::write(&installer_path, &response).await?;
+
+    let java_installation = get_or_download_java(java_version, java_dir, progress_bar).await?;
+    let mut cmd = tokio::process::Command::new(&java_installation.path);
+    cmd.arg("-jar")
+        .arg(std::path::absolute(&installer_path)?)
+        .arg("install")
+        .arg("server")
+        .arg(minecraft_version)
+        .arg(loader_version)
+        .arg(format!(
+            "--install-dir={}",
+            std::path::absolute(server_dir)?.display()
+        ));
+    info!("Running Quilt server installer: {cmd:?}");
+    let output = cmd.output().await?;
+    if !output.status.success() {
+        error!("Command failed: {output:?}");
+        return Err(anyhow::anyhow!(
+            String::from_utf8_lossy(&output.stderr).to_string()
+        ));
+    }
+    tokio::fs::remove_file(&installer_path).await?;
+
+    tokio::fs::File::create(lock_file).await?;
+    Ok(())
+}
+
+async fn download_quilt_metadata(
+    minecraft_version: &str,
+    loader_version: &str,
+    output_dir: &Path,
+) -> anyhow::Result<VersionMetadata> {
+    let quilt_metadata_url =
+        format!("{QUILT_META_BASE_URL}{minecraft_version}/{loader_version}/profile/json");
+    let version_metadata = VersionMetadata::fetch(&quilt_metadata_url).await?;
+    let versions_dir = get_versions_dir(output_dir);
+    version_metadata.save(&versions_dir).await?;
+    Ok(version_metadata)
+}
+
+pub struct QuiltGenerator {
+    version_name: String,
+    vanilla_version_info: VersionInfo,
+    loader_version: Option<String>,
+}
+
+impl QuiltGenerator {
+    pub fn new(
+        version_name: String,
+        vanilla_version_info: VersionInfo,
+        loader_version: Option<String>,
+    ) -> Self {
+        Self {
+            version_name,
+            vanilla_version_info,
+            loader_version,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum QuiltGeneratorError {
+    #[error("No Quilt versions found for game version {0}")]
+    NoVersionsFound(String),
+    #[error("No Quilt installer versions found")]
+    NoInstallersFound,
+}
+
+#[async_trait]
+impl VersionGenerator for QuiltGenerator {
+    async fn generate(&self, work_dir: &Path) -> anyhow::Result<GeneratorResult> {
+        let minecraft_version = self.vanilla_version_info.id.clone();
+
+        info!(
+            "Generating Quilt instance \"{}\", minecraft version {}",
+            self.version_name, minecraft_version
+        );
+
+        info!("Downloading vanilla version metadata");
+        let vanilla_metadata = VersionMetadata::read_or_download(
+            &self.vanilla_version_info.get_parent_metadata_info(),
+            &get_versions_dir(work_dir),
+        )
+        .await?;
+
+        let quilt_version = get_quilt_version(&minecraft_version, &self.loader_version).await?;
+
+        info!("Downloading Quilt version metadata");
+        let quilt_metadata =
+            download_quilt_metadata(&minecraft_version, &quilt_version, work_dir).await?;
+
+        info!("Quilt version \"{}\" generated", self.version_name);
+
+        Ok(GeneratorResult {
+            metadata: vec![vanilla_metadata, quilt_metadata],
+            extra_libs_paths: vec![],
+        })
+    }
+}
@@ -6,6 +6,14 @@ use std::path::Path;
 use std::sync::Mutex;
 
 pub fn setup_logger(logs_path: &Path) {
+    setup_logger_with_hook(logs_path, |_| {});
+}
+
+// the hook sees every record that passes the level filter, e.g. to forward warnings elsewhere
+pub fn setup_logger_with_hook(
+    logs_path: &Path,
+    hook: impl Fn(&log::Record) + Send + Sync + 'static,
+) {
     let log_file = OpenOptions::new()
         .create(true)
         .append(true)
@@ -19,6 +27,7 @@ pub fn setup_logger(logs_path: &Path) {
     builder.parse_default_env();
 
     builder.format(move |buf, record| {
+        hook(record);
         let mut log_file = log_file.lock().unwrap();
         writeln!(log_file, "{} - {}", record.level(), record.args()).unwrap();
         writeln!(buf, "{} - {}", record.level(), record.args())
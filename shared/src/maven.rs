@@ -0,0 +1,165 @@
+use reqwest::Client;
+use serde::Deserialize;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MavenError {
+    #[error("Bad maven coordinates: {0}")]
+    BadCoordinates(String),
+}
+
+/// `group:artifact:version[:classifier][@extension]`, like library names in version metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MavenCoordinates {
+    pub group: String,
+    pub artifact: String,
+    pub version: String,
+    pub classifier: Option<String>,
+    pub extension: String,
+}
+
+impl MavenCoordinates {
+    pub fn parse(name: &str) -> Result<Self, MavenError> {
+        let (name_without_extension, extension) = match name.split_once('@') {
+            Some((name, extension)) => (name, extension),
+            None => (name, "jar"),
+        };
+        let parts: Vec<&str> = name_without_extension.split(':').collect();
+        if !(3..=4).contains(&parts.len())
+            || extension.is_empty()
+            || parts.iter().any(|part| part.is_empty())
+        {
+            return Err(MavenError::BadCoordinates(name.to_string()));
+        }
+        Ok(Self {
+            group: parts[0].to_string(),
+            artifact: parts[1].to_string(),
+            version: parts[2].to_string(),
+            classifier: parts.get(3).map(|classifier| classifier.to_string()),
+            extension: extension.to_string(),
+        })
+    }
+
+    pub fn with_version(&self, version: &str) -> Self {
+        Self {
+            version: version.to_string(),
+            ..self.clone()
+        }
+    }
+
+    fn get_artifact_dir(&self) -> String {
+        format!("{}/{}", self.group.replace('.', "/"), self.artifact)
+    }
+
+    pub fn get_filename(&self) -> String {
+        let suffix = match &self.classifier {
+            Some(classifier) => format!("-{classifier}"),
+            None => "".to_string(),
+        };
+        format!(
+            "{}-{}{suffix}.{}",
+            self.artifact, self.version, self.extension
+        )
+    }
+
+    pub fn get_path(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.get_artifact_dir(),
+            self.version,
+            self.get_filename()
+        )
+    }
+
+    pub fn get_metadata_path(&self) -> String {
+        format!("{}/maven-metadata.xml", self.get_artifact_dir())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Versions {
+    #[serde(default)]
+    pub version: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Versioning {
+    pub latest: Option<String>,
+    pub release: Option<String>,
+    pub versions: Versions,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MavenMetadata {
+    pub versioning: Versioning,
+}
+
+impl MavenMetadata {
+    pub async fn fetch(repository: &str, coordinates: &MavenCoordinates) -> anyhow::Result<Self> {
+        let client = Client::new();
+        let url = format!(
+            "{}/{}",
+            repository.trim_end_matches('/'),
+            coordinates.get_metadata_path()
+        );
+        let response = client.get(url).send().await?.error_for_status()?;
+        Ok(serde_xml_rs::from_str(&response.text().await?)?)
+    }
+
+    // oldest first, as they are listed in the metadata
+    pub fn get_versions(&self) -> &[String] {
+        &self.versioning.versions.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maven_coordinates() {
+        let coordinates = MavenCoordinates::parse("net.fabricmc:fabric-loader:0.16.5").unwrap();
+        assert_eq!(
+            coordinates.get_path(),
+            "net/fabricmc/fabric-loader/0.16.5/fabric-loader-0.16.5.jar"
+        );
+        assert_eq!(
+            coordinates.get_metadata_path(),
+            "net/fabricmc/fabric-loader/maven-metadata.xml"
+        );
+
+        let coordinates = MavenCoordinates::parse("com.example:mod:1.2.0:sources@zip").unwrap();
+        assert_eq!(coordinates.get_filename(), "mod-1.2.0-sources.zip");
+        assert_eq!(
+            coordinates.with_version("1.3.0").get_path(),
+            "com/example/mod/1.3.0/mod-1.3.0-sources.zip"
+        );
+
+        assert!(MavenCoordinates::parse("com.example:mod").is_err());
+        assert!(MavenCoordinates::parse("com.example::1.0").is_err());
+        assert!(MavenCoordinates::parse("com.example:mod:1.0@").is_err());
+    }
+
+    #[test]
+    fn test_maven_metadata() {
+        let metadata: MavenMetadata = serde_xml_rs::from_str(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>com.example</groupId>
+  <artifactId>mod</artifactId>
+  <versioning>
+    <latest>1.1.0-beta</latest>
+    <release>1.0.1</release>
+    <versions>
+      <version>1.0.0</version>
+      <version>1.0.1</version>
+      <version>1.1.0-beta</version>
+    </versions>
+  </versioning>
+</metadata>"#,
+        )
+        .unwrap();
+        assert_eq!(metadata.versioning.release.as_deref(), Some("1.0.1"));
+        assert_eq!(metadata.versioning.latest.as_deref(), Some("1.1.0-beta"));
+        assert_eq!(metadata.get_versions().len(), 3);
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha512};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    files::get_files_in_dir,
+    maven::MavenCoordinates,
+    path_filter::PathFilter,
+    version::{
+        extra_version_metadata::ExtraVersionMetadata, version_manifest::VersionInfo,
+        version_metadata::VersionMetadata,
+    },
+};
+
+pub const MRPACK_INDEX: &str = "modrinth.index.json";
+const OVERRIDES_DIR: &str = "overrides";
+
+#[derive(thiserror::Error, Debug)]
+pub enum MrpackError {
+    #[error("Instance has no vanilla minecraft version")]
+    NoMinecraftVersion,
+    #[error("Size of {0} is unknown")]
+    UnknownSize(String),
+    #[error("{0} has neither a download url nor a local copy")]
+    NoSource(String),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MrpackHashes {
+    pub sha1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MrpackEnv {
+    pub client: String,
+    pub server: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackFile {
+    pub path: String,
+    pub hashes: MrpackHashes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<MrpackEnv>,
+    pub downloads: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+}
+
+/// `modrinth.index.json` of a Modrinth modpack.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackIndex {
+    #[serde(default)]
+    pub format_version: u32,
+    #[serde(default)]
+    pub game: String,
+    #[serde(default)]
+    pub version_id: String,
+    pub name: String,
+    pub files: Vec<MrpackFile>,
+    pub dependencies: BTreeMap<String, String>,
+}
+
+fn get_game_argument(metadata: &VersionMetadata, name: &str) -> Option<String> {
+    let arguments = metadata.arguments.as_ref()?;
+    let mut values = arguments
+        .game
+        .iter()
+        .flat_map(|argument| argument.get_values());
+    values.find(|value| *value == name)?;
+    values.next().map(str::to_string)
+}
+
+// the modrinth name and the version of the loader
+fn get_loader(
+    metadata: &[VersionMetadata],
+    minecraft_version: &str,
+) -> Option<(&'static str, String)> {
+    let libraries: Vec<MavenCoordinates> = metadata
+        .iter()
+        .flat_map(|metadata| metadata.libraries.iter())
+        .filter_map(|library| MavenCoordinates::parse(&library.get_full_name()).ok())
+        .collect();
+    let get_library_version = |group: &str, artifact: &str| {
+        libraries
+            .iter()
+            .find(|library| library.group == group && library.artifact == artifact)
+            .map(|library| library.version.clone())
+    };
+
+    if let Some(version) = get_library_version("org.quiltmc", "quilt-loader") {
+        return Some(("quilt-loader", version));
+    }
+    if let Some(version) = get_library_version("net.fabricmc", "fabric-loader") {
+        return Some(("fabric-loader", version));
+    }
+    if let Some(version) = metadata
+        .iter()
+        .find_map(|metadata| get_game_argument(metadata, "--fml.neoForgeVersion"))
+    {
+        return Some(("neoforge", version));
+    }
+    if let Some(version) = metadata
+        .iter()
+        .find_map(|metadata| get_game_argument(metadata, "--fml.forgeVersion"))
+    {
+        // the first neoforge versions still used the forge arguments
+        let is_neoforge = libraries
+            .iter()
+            .any(|library| library.group.starts_with("net.neoforged"));
+        return Some((if is_neoforge { "neoforge" } else { "forge" }, version));
+    }
+
+    // older forge versions only have the library, versioned as "<minecraft>-<forge>[-<minecraft>]"
+    get_library_version("net.minecraftforge", "forge").map(|version| {
+        let version = version
+            .strip_prefix(&format!("{minecraft_version}-"))
+            .unwrap_or(&version);
+        let version = version
+            .strip_suffix(&format!("-{minecraft_version}"))
+            .unwrap_or(version);
+        ("forge", version.to_string())
+    })
+}
+
+/// Minecraft version and loader of the instance, `metadata` is the whole inheritance chain.
+pub fn get_dependencies(
+    metadata: &[VersionMetadata],
+) -> Result<BTreeMap<String, String>, MrpackError> {
+    let minecraft_version = metadata
+        .iter()
+        .find(|metadata| metadata.inherits_from.is_none())
+        .map(|metadata| metadata.id.clone())
+        .ok_or(MrpackError::NoMinecraftVersion)?;
+
+    let mut dependencies = BTreeMap::new();
+    if let Some((loader, version)) = get_loader(metadata, &minecraft_version) {
+        dependencies.insert(loader.to_string(), version);
+    }
+    dependencies.insert("minecraft".to_string(), minecraft_version);
+    Ok(dependencies)
+}
+
+async fn hash_file_sha512(path: &Path) -> anyhow::Result<String> {
+    Ok(hex::encode(Sha512::digest(tokio::fs::read(path).await?)))
+}
+
+fn is_downloadable(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// Writes an instance as a .mrpack. Files with a download url are listed in the index,
+/// the rest is packed into the archive as overrides.
+pub struct MrpackExporter {
+    index: MrpackIndex,
+    // path relative to the minecraft directory -> local file
+    overrides: BTreeMap<String, PathBuf>,
+}
+
+impl MrpackExporter {
+    pub fn new(
+        version_info: &VersionInfo,
+        metadata: &[VersionMetadata],
+    ) -> Result<Self, MrpackError> {
+        let version_id = match version_info.built_at {
+            Some(built_at) => built_at.to_string(),
+            None => version_info.id.clone(),
+        };
+        Ok(Self {
+            index: MrpackIndex {
+                format_version: 1,
+                game: "minecraft".to_string(),
+                version_id,
+                name: version_info.get_name(),
+                files: vec![],
+                dependencies: get_dependencies(metadata)?,
+            },
+            overrides: BTreeMap::new(),
+        })
+    }
+
+    /// Add the include objects, `object_sources` maps sha1 to local copies of the objects.
+    /// With `embed` every object that has a local copy is packed instead of downloaded.
+    pub async fn add_objects(
+        &mut self,
+        extra_metadata: &ExtraVersionMetadata,
+        object_sources: &HashMap<String, PathBuf>,
+        embed: bool,
+    ) -> anyhow::Result<()> {
+        for include in &extra_metadata.include {
+            if include.rules.is_some() {
+                warn!(
+                    "Skipping {}, modpacks can't have platform-specific files",
+                    include.path
+                );
+                continue;
+            }
+            // players choose optional files when they import the pack
+            let env = include.optional_group.as_ref().map(|_| MrpackEnv {
+                client: "optional".to_string(),
+                server: "optional".to_string(),
+            });
+
+            for object in &include.objects {
+                let source = object_sources
+                    .get(&object.sha1)
+                    .filter(|source| source.is_file());
+                if embed || !is_downloadable(&object.url) {
+                    match source {
+                        Some(source) => {
+                            self.overrides.insert(object.path.clone(), source.clone());
+                            continue;
+                        }
+                        None if !is_downloadable(&object.url) => {
+                            return Err(MrpackError::NoSource(object.path.clone()).into());
+                        }
+                        None => {}
+                    }
+                }
+
+                let file_size = match (object.size, source) {
+                    (Some(size), _) => size,
+                    (None, Some(source)) => tokio::fs::metadata(source).await?.len(),
+                    (None, None) => {
+                        return Err(MrpackError::UnknownSize(object.path.clone()).into())
+                    }
+                };
+                let sha512 = match source {
+                    Some(source) => Some(hash_file_sha512(source).await?),
+                    None => None,
+                };
+                self.index.files.push(MrpackFile {
+                    path: object.path.clone(),
+                    hashes: MrpackHashes {
+                        sha1: object.sha1.clone(),
+                        sha512,
+                    },
+                    env: env.clone(),
+                    downloads: vec![object.url.clone()],
+                    file_size: Some(file_size),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Pack the files of the minecraft directory `dir` that match the filter.
+    pub fn add_overrides(&mut self, dir: &Path, path_filter: &PathFilter) -> anyhow::Result<()> {
+        for file in get_files_in_dir(dir)? {
+            let rel_path = file.strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+            if path_filter.matches(&rel_path) {
+                self.overrides.insert(rel_path, file);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, archive_path: &Path) -> anyhow::Result<()> {
+        let mut archive = ZipWriter::new(File::create(archive_path)?);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        archive.start_file(MRPACK_INDEX, options)?;
+        archive.write_all(&serde_json::to_vec_pretty(&self.index)?)?;
+        for (rel_path, source) in &self.overrides {
+            archive.start_file(format!("{OVERRIDES_DIR}/{rel_path}"), options)?;
+            io::copy(&mut File::open(source)?, &mut archive)?;
+        }
+        archive.finish()?;
+
+        info!(
+            "Exported {} to {}, {} downloaded files, {} packed files",
+            self.index.name,
+            archive_path.display(),
+            self.index.files.len(),
+            self.overrides.len()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(json: &str) -> VersionMetadata {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_get_dependencies() {
+        let vanilla = || metadata(r#"{"id": "1.20.1", "libraries": [], "mainClass": "Main"}"#);
+
+        let dependencies = get_dependencies(&[vanilla()]).unwrap();
+        assert_eq!(dependencies.len(), 1);
+        assert_eq!(dependencies["minecraft"], "1.20.1");
+
+        let fabric = metadata(
+            r#"{
+                "id": "fabric-loader-0.16.5-1.20.1",
+                "inheritsFrom": "1.20.1",
+                "libraries": [
+                    {"name": "net.fabricmc:intermediary:1.20.1"},
+                    {"name": "net.fabricmc:fabric-loader:0.16.5"}
+                ],
+                "mainClass": "net.fabricmc.loader.impl.launch.knot.KnotClient"
+            }"#,
+        );
+        let dependencies = get_dependencies(&[vanilla(), fabric]).unwrap();
+        assert_eq!(dependencies["fabric-loader"], "0.16.5");
+        assert_eq!(dependencies["minecraft"], "1.20.1");
+
+        let forge = metadata(
+            r#"{
+                "id": "1.20.1-forge-47.2.0",
+                "inheritsFrom": "1.20.1",
+                "arguments": {
+                    "game": ["--launchTarget", "forgeclient", "--fml.forgeVersion", "47.2.0"],
+                    "jvm": []
+                },
+                "libraries": [{"name": "net.minecraftforge:fmlloader:1.20.1-47.2.0"}],
+                "mainClass": "cpw.mods.bootstraplauncher.BootstrapLauncher"
+            }"#,
+        );
+        let dependencies = get_dependencies(&[vanilla(), forge]).unwrap();
+        assert_eq!(dependencies["forge"], "47.2.0");
+
+        let legacy_forge = metadata(
+            r#"{
+                "id": "1.7.10-Forge10.13.4.1614-1.7.10",
+                "inheritsFrom": "1.7.10",
+                "libraries": [{"name": "net.minecraftforge:forge:1.7.10-10.13.4.1614-1.7.10"}],
+                "mainClass": "net.minecraft.launchwrapper.Launch"
+            }"#,
+        );
+        let legacy_vanilla = metadata(r#"{"id": "1.7.10", "libraries": [], "mainClass": "Main"}"#);
+        let dependencies = get_dependencies(&[legacy_vanilla, legacy_forge]).unwrap();
+        assert_eq!(dependencies["forge"], "10.13.4.1614");
+        assert_eq!(dependencies["minecraft"], "1.7.10");
+
+        let neoforge = metadata(
+            r#"{
+                "id": "neoforge-21.1.65",
+                "inheritsFrom": "1.21.1",
+                "arguments": {"game": ["--fml.neoForgeVersion", "21.1.65"], "jvm": []},
+                "libraries": [],
+                "mainClass": "cpw.mods.bootstraplauncher.BootstrapLauncher"
+            }"#,
+        );
+        let neoforge_vanilla =
+            metadata(r#"{"id": "1.21.1", "libraries": [], "mainClass": "Main"}"#);
+        let dependencies = get_dependencies(&[neoforge_vanilla, neoforge]).unwrap();
+        assert_eq!(dependencies["neoforge"], "21.1.65");
+
+        assert!(get_dependencies(&[]).is_err());
+    }
+}
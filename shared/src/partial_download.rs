@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+// stored next to a partial file, used to check that the remote file hasn't changed
+#[derive(Serialize, Deserialize)]
+struct ResumeInfo {
+    url: String,
+    validator: String,
+}
+
+pub struct PartialDownload {
+    pub response: Response,
+    pub file: tokio::fs::File,
+    // number of bytes that are already in the file
+    pub offset: u64,
+}
+
+pub fn get_tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    PathBuf::from(tmp_path)
+}
+
+fn get_resume_info_path(tmp_path: &Path) -> PathBuf {
+    let mut info_path = tmp_path.as_os_str().to_owned();
+    info_path.push(".resume");
+    PathBuf::from(info_path)
+}
+
+async fn read_resume_info(tmp_path: &Path) -> Option<ResumeInfo> {
+    let data = tokio::fs::read(get_resume_info_path(tmp_path)).await.ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+// a strong etag is preferred, weak ones can't be used in If-Range
+fn get_validator(response: &Response) -> Option<String> {
+    let headers = response.headers();
+    if let Some(etag) = headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+        if !etag.starts_with("W/") {
+            return Some(etag.to_string());
+        }
+    }
+    headers
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+fn is_expected_range(response: &Response, offset: u64) -> bool {
+    response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(&format!("bytes {offset}-")))
+}
+
+async fn save_resume_info(url: &str, tmp_path: &Path, response: &Response) -> anyhow::Result<()> {
+    let info_path = get_resume_info_path(tmp_path);
+    match get_validator(response) {
+        Some(validator) => {
+            let info = ResumeInfo {
+                url: url.to_string(),
+                validator,
+            };
+            tokio::fs::write(&info_path, serde_json::to_vec(&info)?).await?;
+        }
+        None => {
+            let _ = tokio::fs::remove_file(&info_path).await;
+        }
+    }
+    Ok(())
+}
+
+/// Start downloading `url` into `tmp_path`, continuing a previously interrupted download
+/// with a Range request if the remote file is unchanged.
+/// Falls back to downloading the whole file if the server doesn't support resuming.
+pub async fn start_download(
+    client: &Client,
+    url: &str,
+    tmp_path: &Path,
+) -> anyhow::Result<PartialDownload> {
+    if let Some(parent_dir) = tmp_path.parent() {
+        tokio::fs::create_dir_all(parent_dir).await?;
+    }
+
+    let existing_len = tokio::fs::metadata(tmp_path)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let resume_info = read_resume_info(tmp_path)
+        .await
+        .filter(|info| info.url == url && existing_len > 0);
+
+    if let Some(info) = resume_info {
+        let response = client
+            .get(url)
+            .header(ACCEPT_ENCODING, "identity")
+            .header(RANGE, format!("bytes={existing_len}-"))
+            .header(IF_RANGE, &info.validator)
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::PARTIAL_CONTENT
+            && is_expected_range(&response, existing_len)
+        {
+            debug!("Resuming download of {url} from byte {existing_len}");
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(tmp_path)
+                .await?;
+            return Ok(PartialDownload {
+                response,
+                file,
+                offset: existing_len,
+            });
+        }
+
+        // the file has changed or the server ignored the range
+        if response.status() == StatusCode::OK {
+            debug!("Server sent the whole file for {url}, restarting download");
+            let file = tokio::fs::File::create(tmp_path).await?;
+            save_resume_info(url, tmp_path, &response).await?;
+            return Ok(PartialDownload {
+                response,
+                file,
+                offset: 0,
+            });
+        }
+
+        if response.status() != StatusCode::RANGE_NOT_SATISFIABLE {
+            response.error_for_status_ref()?;
+        }
+        debug!("Can't resume download of {url}, restarting");
+    }
+
+    // compressed responses can't be resumed by byte offset
+    let response = client
+        .get(url)
+        .header(ACCEPT_ENCODING, "identity")
+        .send()
+        .await?
+        .error_for_status()?;
+    save_resume_info(url, tmp_path, &response).await?;
+    let file = tokio::fs::File::create(tmp_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to create temp file {:?}: {}", tmp_path, e))?;
+    Ok(PartialDownload {
+        response,
+        file,
+        offset: 0,
+    })
+}
+
+/// Feed the already downloaded part of the file into `update`, e.g. a hasher.
+pub async fn read_downloaded_part(
+    tmp_path: &Path,
+    mut update: impl FnMut(&[u8]),
+) -> anyhow::Result<()> {
+    let mut file = tokio::fs::File::open(tmp_path).await?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        update(&buffer[..n]);
+    }
+    Ok(())
+}
+
+/// Forget the resume info once the download is complete.
+pub async fn finish_download(tmp_path: &Path) {
+    let _ = tokio::fs::remove_file(get_resume_info_path(tmp_path)).await;
+}
+
+/// Remove a partial file that can't be used anymore.
+pub async fn discard_download(tmp_path: &Path) {
+    let _ = tokio::fs::remove_file(tmp_path).await;
+    finish_download(tmp_path).await;
+}
@@ -0,0 +1,64 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+// `*` doesn't match `/`, so that `mods/*.jar` doesn't include subdirectories
+fn build_glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+fn build_glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(build_glob(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Include and exclude globs of an include rule, matched against paths relative to the
+/// minecraft directory, such as `mods/sodium.jar`.
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        Ok(Self {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(build_glob_set(include)?)
+            },
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.replace('\\', "/");
+        self.include.as_ref().is_none_or(|set| set.is_match(&path)) && !self.exclude.is_match(&path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter::new(
+            &["mods/*.jar".to_string(), "config/**".to_string()],
+            &["config/**/*.bak".to_string(), "**/.DS_Store".to_string()],
+        )
+        .unwrap();
+
+        assert!(filter.matches("mods/sodium.jar"));
+        assert!(!filter.matches("mods/disabled/sodium.jar"));
+        assert!(!filter.matches("mods/notes.txt"));
+        assert!(filter.matches("config/sodium/options.json"));
+        assert!(!filter.matches("config/sodium/options.json.bak"));
+        assert!(!filter.matches("config/.DS_Store"));
+
+        let filter = PathFilter::new(&[], &["**/*.log".to_string()]).unwrap();
+        assert!(filter.matches("options.txt"));
+        assert!(!filter.matches("logs/latest.log"));
+    }
+}
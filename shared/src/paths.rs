@@ -115,6 +115,12 @@ pub fn get_extra_metadata_path(versions_extra_dir: &Path, version_name: &str) ->
     parent_created(versions_extra_dir.join(get_rel_extra_metadata_path(version_name)))
 }
 
+// files that change between builds are published under their hash,
+// so a new build never rewrites a file that the current manifest refers to
+pub fn get_rel_object_path(sha1: &str) -> PathBuf {
+    PathBuf::from("objects").join(&sha1[..2]).join(sha1)
+}
+
 pub fn get_asset_index_path(assets_dir: &Path, asset_index: &str) -> PathBuf {
     parent_created(
         assets_dir
@@ -132,3 +138,7 @@ const AUTHLIB_INJECTOR_NAME: &str = "authlib-injector.jar";
 pub fn get_authlib_injector_path(data_dir: &Path) -> PathBuf {
     parent_created(get_libraries_dir(data_dir).join(AUTHLIB_INJECTOR_NAME))
 }
+
+pub fn get_hash_cache_path(data_dir: &Path) -> PathBuf {
+    parent_created(data_dir.join("hash_cache.json"))
+}
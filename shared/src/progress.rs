@@ -22,6 +22,8 @@ pub trait ProgressBar<M>: Sync + Send {
     }
 
     fn set_unit(&self, unit: Unit);
+
+    fn clear_unit(&self);
 }
 
 pub struct NoProgressBar;
@@ -36,6 +38,8 @@ impl<M> ProgressBar<M> for NoProgressBar {
     fn finish(&self) {}
 
     fn set_unit(&self, _unit: Unit) {}
+
+    fn clear_unit(&self) {}
 }
 
 pub fn no_progress_bar() -> Arc<dyn ProgressBar<i32> + Send + Sync> {
@@ -147,6 +151,8 @@ mod tests {
         }
 
         fn set_unit(&self, _unit: Unit) {}
+
+        fn clear_unit(&self) {}
     }
 
     #[tokio::test]
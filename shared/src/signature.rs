@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer as _, Verifier as _, VerifyingKey};
+
+pub use ed25519_dalek::SigningKey;
+
+const SIGNATURE_SUFFIX: &str = ".sig";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignatureError {
+    #[error("Invalid signing key, expected 32 hex-encoded bytes")]
+    InvalidSigningKey,
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Signature does not match any trusted key")]
+    Untrusted,
+}
+
+pub fn get_signature_url(url: &str) -> String {
+    format!("{url}{SIGNATURE_SUFFIX}")
+}
+
+pub fn get_signature_path(path: &Path) -> std::path::PathBuf {
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(SIGNATURE_SUFFIX);
+    signature_path.into()
+}
+
+pub fn generate_signing_key() -> SigningKey {
+    SigningKey::from_bytes(&rand::random())
+}
+
+// the key file contains the hex-encoded 32 byte secret key
+pub async fn read_signing_key(path: &Path) -> anyhow::Result<SigningKey> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let bytes = hex::decode(content.trim()).map_err(|_| SignatureError::InvalidSigningKey)?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| SignatureError::InvalidSigningKey)?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+pub fn encode_signing_key(key: &SigningKey) -> String {
+    hex::encode(key.to_bytes())
+}
+
+pub fn get_public_key(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().to_bytes())
+}
+
+pub fn sign(key: &SigningKey, data: &[u8]) -> String {
+    hex::encode(key.sign(data).to_bytes())
+}
+
+fn parse_public_key(public_key: &str) -> Result<VerifyingKey, SignatureError> {
+    let invalid = || SignatureError::InvalidPublicKey(public_key.to_string());
+    let bytes: [u8; 32] = hex::decode(public_key.trim())
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| invalid())
+}
+
+/// Check that `signature` of `data` was made by one of `trusted_keys` (hex-encoded public keys).
+pub fn verify(data: &[u8], signature: &str, trusted_keys: &[&str]) -> anyhow::Result<()> {
+    let signature: [u8; 64] = hex::decode(signature.trim())
+        .map_err(|_| SignatureError::InvalidSignature)?
+        .try_into()
+        .map_err(|_| SignatureError::InvalidSignature)?;
+    let signature = Signature::from_bytes(&signature);
+
+    for key in trusted_keys {
+        if parse_public_key(key)?.verify(data, &signature).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(SignatureError::Untrusted.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = generate_signing_key();
+        let other_key = generate_signing_key();
+        let public_key = get_public_key(&key);
+        let other_public_key = get_public_key(&other_key);
+        let data = b"{\"versions\":[]}";
+
+        let signature = sign(&key, data);
+        verify(data, &signature, &[&other_public_key, &public_key]).unwrap();
+        assert!(verify(data, &signature, &[&other_public_key]).is_err());
+        assert!(verify(b"{\"versions\":[1]}", &signature, &[&public_key]).is_err());
+    }
+}
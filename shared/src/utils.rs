@@ -1,4 +1,8 @@
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
 use crate::version::version_manifest::{VersionInfo, VersionManifest};
 
@@ -64,3 +68,22 @@ pub fn is_connect_error(e: &anyhow::Error) -> bool {
         || error_str.contains("Network is unreachable")
         || error_str.contains("Connection timed out")
 }
+
+// one async lock per key, e.g. to keep parallel jobs from installing the same thing twice
+#[derive(Default)]
+pub struct KeyedLocks {
+    locks: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl KeyedLocks {
+    pub async fn lock(&self, key: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
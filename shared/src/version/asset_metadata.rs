@@ -16,6 +16,8 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
 pub struct ObjectData {
     pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -50,6 +52,7 @@ impl AssetsMetadata {
             url: asset_index.url.clone(),
             remote_sha1: Some(asset_index.sha1.clone()),
             path: asset_index_path.clone(),
+            size: asset_index.size,
         };
         let check_entries = vec![check_entry];
         let download_entries =
@@ -83,6 +86,7 @@ impl AssetsMetadata {
                 } else {
                     None
                 },
+                size: object.size,
             }
         }));
 
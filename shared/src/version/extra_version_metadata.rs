@@ -3,15 +3,20 @@ use std::path::Path;
 use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::{files::CheckEntry, paths::get_extra_metadata_path};
+use crate::{files::CheckEntry, path_filter::PathFilter, paths::get_extra_metadata_path};
 
-use super::{version_manifest::VersionInfo, version_metadata::Library};
+use super::{
+    version_manifest::VersionInfo,
+    version_metadata::{rules_apply, Library, Rule},
+};
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Object {
     pub path: String,
     pub sha1: String,
     pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
@@ -72,7 +77,18 @@ fn yes() -> bool {
     true
 }
 
-#[derive(Deserialize, Serialize)]
+/// A group of files players can turn off, e.g. a minimap or shaders.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct OptionalGroup {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+
+    #[serde(default = "yes")]
+    pub enabled_by_default: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Include {
     pub path: String,
 
@@ -85,11 +101,55 @@ pub struct Include {
     #[serde(default)]
     pub recursive: bool,
 
+    // globs relative to the minecraft directory, files outside of them are left untouched
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    // same as library rules, the include is skipped on other platforms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<Rule>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optional_group: Option<OptionalGroup>,
+
     #[serde(default)]
     pub objects: Vec<Object>,
 }
 
-#[derive(Deserialize, Serialize)]
+impl Include {
+    pub fn applies_to_os(&self, os_name: &str, arch: &str) -> bool {
+        match &self.rules {
+            Some(rules) => rules_apply(rules, os_name, arch),
+            None => true,
+        }
+    }
+
+    pub fn get_path_filter(&self) -> anyhow::Result<PathFilter> {
+        PathFilter::new(&self.include, &self.exclude)
+    }
+}
+
+/// A Java runtime archive rehosted on the download server.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct JavaRuntime {
+    // major version, same as in the javaVersion of the version metadata
+    pub version: String,
+
+    // same names as in library rules
+    pub os: String,
+    pub arch: String,
+
+    // the archive unpacks into a directory named like the archive
+    pub filename: String,
+    pub url: String,
+    pub sha1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ExtraVersionMetadata {
     #[serde(default)]
     pub auth_backend: Option<AuthBackend>,
@@ -104,9 +164,39 @@ pub struct ExtraVersionMetadata {
     pub extra_forge_libs: Vec<Library>,
 
     pub recommended_xmx: Option<String>,
+
+    // the launcher downloads java from here before falling back to Azul
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub java_runtimes: Vec<JavaRuntime>,
 }
 
 impl ExtraVersionMetadata {
+    /// Optional groups of all includes, the first definition of each id wins.
+    pub fn get_optional_groups(&self) -> Vec<&OptionalGroup> {
+        let mut groups: Vec<&OptionalGroup> = vec![];
+        for group in self
+            .include
+            .iter()
+            .filter_map(|x| x.optional_group.as_ref())
+        {
+            if !groups.iter().any(|x| x.id == group.id) {
+                groups.push(group);
+            }
+        }
+        groups
+    }
+
+    pub fn get_java_runtime(
+        &self,
+        version: &str,
+        os_name: &str,
+        arch: &str,
+    ) -> Option<&JavaRuntime> {
+        self.java_runtimes.iter().find(|runtime| {
+            runtime.version == version && runtime.os == os_name && runtime.arch == arch
+        })
+    }
+
     pub async fn read_local(
         version_info: &VersionInfo,
         versions_extra_dir: &Path,
@@ -137,6 +227,7 @@ impl ExtraVersionMetadata {
             url: url.clone(),
             remote_sha1: Some(sha1.clone()),
             path: get_extra_metadata_path(versions_extra_dir, &version_info.get_name()),
+            size: None,
         })
     }
 
@@ -148,3 +239,72 @@ impl ExtraVersionMetadata {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include_applies_to_os() {
+        let include: Include = serde_json::from_str(
+            r#"{
+                "path": "mods/natives-linux-arm64.jar",
+                "rules": [{"action": "allow", "os": {"name": "linux", "arch": "arm64"}}]
+            }"#,
+        )
+        .unwrap();
+        assert!(include.applies_to_os("linux", "arm64"));
+        assert!(!include.applies_to_os("linux", "x86_64"));
+        assert!(!include.applies_to_os("windows", "arm64"));
+
+        let include: Include = serde_json::from_str(
+            r#"{
+                "path": "shaderpacks",
+                "rules": [
+                    {"action": "allow"},
+                    {"action": "disallow", "os": {"name": "osx"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(include.applies_to_os("windows", "x86_64"));
+        assert!(!include.applies_to_os("osx", "arm64"));
+    }
+
+    #[test]
+    fn test_get_java_runtime() {
+        let extra_metadata: ExtraVersionMetadata = serde_json::from_str(
+            r#"{
+                "java_runtimes": [
+                    {
+                        "version": "21",
+                        "os": "linux",
+                        "arch": "x86_64",
+                        "filename": "zulu21-linux_x64.tar.gz",
+                        "url": "https://example.com/objects/ab/ab12",
+                        "sha1": "ab12"
+                    },
+                    {
+                        "version": "21",
+                        "os": "windows",
+                        "arch": "x86_64",
+                        "filename": "zulu21-win_x64.zip",
+                        "url": "https://example.com/objects/cd/cd34",
+                        "sha1": "cd34"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let runtime = extra_metadata
+            .get_java_runtime("21", "windows", "x86_64")
+            .unwrap();
+        assert_eq!(runtime.filename, "zulu21-win_x64.zip");
+        assert!(extra_metadata
+            .get_java_runtime("21", "linux", "arm64")
+            .is_none());
+        assert!(extra_metadata
+            .get_java_runtime("17", "linux", "x86_64")
+            .is_none());
+    }
+}
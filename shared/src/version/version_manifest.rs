@@ -3,6 +3,8 @@ use std::path::Path;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use crate::signature::{self, get_signature_url};
+
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct MetadataInfo {
     pub id: String,
@@ -10,6 +12,23 @@ pub struct MetadataInfo {
     pub sha1: String,
 }
 
+// a previously published build of an instance, kept under versioned paths
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoricBuild {
+    // unix timestamp in seconds
+    pub built_at: u64,
+
+    pub id: String,
+    pub url: String,
+    pub sha1: String,
+
+    #[serde(default)]
+    pub inherits_from: Vec<MetadataInfo>,
+
+    pub extra_metadata_url: Option<String>,
+    pub extra_metadata_sha1: Option<String>,
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct VersionInfo {
     pub id: String,
@@ -23,6 +42,18 @@ pub struct VersionInfo {
 
     pub extra_metadata_url: Option<String>,
     pub extra_metadata_sha1: Option<String>,
+
+    // versions with the same name and different channels are variants of one instance,
+    // `None` is the default (stable) channel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub built_at: Option<u64>,
+
+    // older builds, newest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoricBuild>,
 }
 
 impl VersionInfo {
@@ -33,6 +64,37 @@ impl VersionInfo {
         }
     }
 
+    pub fn is_same_instance(&self, other: &VersionInfo) -> bool {
+        self.get_name() == other.get_name() && self.channel == other.channel
+    }
+
+    pub fn without_history(&self) -> VersionInfo {
+        VersionInfo {
+            history: vec![],
+            ..self.clone()
+        }
+    }
+
+    /// Version info of the build made at `built_at`, either the latest one or one from the history.
+    pub fn get_build(&self, built_at: u64) -> Option<VersionInfo> {
+        if self.built_at == Some(built_at) {
+            return Some(self.without_history());
+        }
+        let build = self.history.iter().find(|b| b.built_at == built_at)?;
+        Some(VersionInfo {
+            id: build.id.clone(),
+            url: build.url.clone(),
+            sha1: build.sha1.clone(),
+            name: Some(self.get_name()),
+            inherits_from: build.inherits_from.clone(),
+            extra_metadata_url: build.extra_metadata_url.clone(),
+            extra_metadata_sha1: build.extra_metadata_sha1.clone(),
+            channel: self.channel.clone(),
+            built_at: Some(build.built_at),
+            history: vec![],
+        })
+    }
+
     pub fn get_parent_metadata_info(&self) -> MetadataInfo {
         match self.inherits_from.first() {
             Some(parent_info) => parent_info.clone(),
@@ -82,6 +144,27 @@ impl VersionManifest {
         Ok(res)
     }
 
+    /// Fetch the manifest and check its detached signature against `trusted_keys`.
+    pub async fn fetch_signed(url: &str, trusted_keys: &[&str]) -> anyhow::Result<Self> {
+        let client = Client::new();
+        let data = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let signature = client
+            .get(get_signature_url(url))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        signature::verify(&data, &signature, trusted_keys)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
     pub async fn read_local(manifest_path: &Path) -> anyhow::Result<Self> {
         let manifest_file = tokio::fs::read(manifest_path).await?;
         let manifest: Self = serde_json::from_slice(&manifest_file)?;
@@ -112,8 +195,7 @@ impl VersionManifest {
         version_info: VersionInfo,
         manifest_path: &Path,
     ) -> anyhow::Result<()> {
-        self.versions
-            .retain(|i| i.get_name() != version_info.get_name());
+        self.versions.retain(|i| !i.is_same_instance(&version_info));
         self.versions.push(version_info);
         self.save_to_file(manifest_path).await
     }
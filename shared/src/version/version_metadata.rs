@@ -9,6 +9,7 @@ use tokio::{fs, io::AsyncReadExt as _};
 use crate::{
     adaptive_download::download_files,
     files::{self, CheckEntry},
+    maven::MavenCoordinates,
     paths::get_metadata_path,
     progress,
 };
@@ -81,7 +82,7 @@ impl Rule {
     }
 }
 
-fn rules_apply(rules: &[Rule], os_name: &str, arch: &str) -> bool {
+pub fn rules_apply(rules: &[Rule], os_name: &str, arch: &str) -> bool {
     let mut some_allowed = false;
     for rule in rules {
         if let Some(is_allowed) = rule.allowed_on_os(os_name, arch) {
@@ -156,6 +157,8 @@ pub struct AssetIndex {
     pub id: String,
     pub sha1: String,
     pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -168,6 +171,8 @@ pub struct JavaVersion {
 pub struct Download {
     pub sha1: String,
     pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
 impl Download {
@@ -176,6 +181,7 @@ impl Download {
             url: self.url.clone(),
             remote_sha1: Some(self.sha1.clone()),
             path: path.to_path_buf(),
+            size: self.size,
         }
     }
 
@@ -203,11 +209,11 @@ pub struct Library {
 }
 
 impl Library {
-    pub fn from_download(name: String, url: String, sha1: String) -> Self {
+    pub fn from_download(name: String, url: String, sha1: String, size: Option<u64>) -> Self {
         Library {
             name,
             downloads: Some(LibraryDownloads {
-                artifact: Some(Download { url, sha1 }),
+                artifact: Some(Download { url, sha1, size }),
                 classifiers: None,
             }),
             rules: None,
@@ -218,21 +224,11 @@ impl Library {
     }
 
     pub fn get_path_from_name(&self) -> String {
-        let full_name = self.name.clone();
-        let mut parts: Vec<&str> = full_name.split(':').collect();
-        if parts.len() != 4 {
-            parts.push("");
+        match MavenCoordinates::parse(&self.name) {
+            Ok(coordinates) => coordinates.get_path(),
+            // not a maven name, the download fails with the path in the error
+            Err(_) => self.name.replace(':', "/"),
         }
-        let (pkg, name, version, suffix) = (parts[0], parts[1], parts[2], parts[3]);
-        // neoforge adds "@jar" to the version, so we need to remove it
-        let version = version.split("@jar").next().unwrap();
-        let pkg_path = pkg.replace('.', "/");
-        let suffix = if suffix.is_empty() {
-            "".to_string()
-        } else {
-            format!("-{suffix}")
-        };
-        format!("{pkg_path}/{name}/{version}/{name}-{version}{suffix}.jar")
     }
 
     pub fn get_library_path(&self, libraries_dir: &Path) -> Option<PathBuf> {
@@ -305,6 +301,7 @@ impl Library {
                 url: format!("{}/{}", self.get_url(), self.get_path_from_name()),
                 remote_sha1: self.sha1.clone(),
                 path: libraries_dir.join(self.get_path_from_name()),
+                size: None,
             })
         }
     }
@@ -373,6 +370,8 @@ impl Library {
 #[derive(Deserialize, Serialize)]
 pub struct Downloads {
     pub client: Option<Download>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<Download>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -456,6 +455,7 @@ impl VersionMetadata {
             url,
             remote_sha1: Some(sha1),
             path,
+            size: None,
         }
     }
 
@@ -490,7 +490,37 @@ impl VersionMetadata {
     pub async fn save(&self, versions_dir: &Path) -> anyhow::Result<()> {
         let version_path = get_metadata_path(versions_dir, &self.id);
         let content = serde_json::to_string(self)?;
-        fs::write(version_path, content).await?;
-        Ok(())
+        // instances built in parallel can save the same loader metadata
+        files::write_atomic(&version_path, content).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_path_from_name() {
+        let library = |name: &str| Library {
+            name: name.to_string(),
+            downloads: None,
+            rules: None,
+            url: None,
+            sha1: None,
+            natives: None,
+        };
+        assert_eq!(
+            library("org.ow2.asm:asm:9.6").get_path_from_name(),
+            "org/ow2/asm/asm/9.6/asm-9.6.jar"
+        );
+        assert_eq!(
+            library("org.lwjgl:lwjgl:3.3.3:natives-windows").get_path_from_name(),
+            "org/lwjgl/lwjgl/3.3.3/lwjgl-3.3.3-natives-windows.jar"
+        );
+        // neoforge names its jars with an explicit extension
+        assert_eq!(
+            library("net.neoforged:neoforge:21.1.77:universal@jar").get_path_from_name(),
+            "net/neoforged/neoforge/21.1.77/neoforge-21.1.77-universal.jar"
+        );
     }
 }